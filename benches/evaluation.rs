@@ -0,0 +1,80 @@
+//! Compares the token walker behind `Parser::execute` with the bytecode run
+//! by `CompiledExpr::eval`, and with `--features jit` the native code from
+//! `CompiledExpr::jit`, per row of `CompiledExpr::eval_batch`.
+//! Run with `cargo bench --bench evaluation`.
+#![allow(clippy::needless_return)]
+
+use std::{hint::black_box, time::Instant};
+
+use arth_lexer::{CompiledExpr, FloatPolicy, Lexer, Parser};
+
+const EXPRESSION: &str = "2 * pi * r + sin r * (r - 1) / 3 ^ 2 - log (r + 10)";
+const ITERATIONS: u32 = 200_000;
+const ROWS: u32 = 1_000;
+
+/// Times `run`, which evaluates the expression `evaluations` times per call.
+fn time<F, T>(label: &str, evaluations: u32, mut run: F) -> f64
+where
+    F: FnMut() -> T,
+{
+    let calls = ITERATIONS / evaluations;
+
+    for _ in 0..calls / 10 {
+        black_box(run());
+    }
+
+    let start = Instant::now();
+    for _ in 0..calls {
+        black_box(run());
+    }
+    let per_eval = start.elapsed().as_nanos() as f64 / f64::from(calls * evaluations);
+
+    println!("{label:<14} {per_eval:>8.1} ns/eval");
+
+    return per_eval;
+}
+
+fn main() {
+    let tokens = Lexer::new().lex(EXPRESSION).unwrap();
+    let mut parser = Parser::new();
+    parser.registry_mut().set_variable("r", 2.5).unwrap();
+    let queue = parser.parse(tokens).unwrap();
+
+    let compiled = CompiledExpr::new(EXPRESSION, queue.clone(), FloatPolicy::Strict).unwrap();
+    let bindings = [2.5];
+
+    assert_eq!(
+        parser.execute(&queue).unwrap(),
+        compiled.eval(&bindings).unwrap()
+    );
+
+    println!("{EXPRESSION}");
+    let walker = time("token walker", 1, || {
+        parser.execute(black_box(&queue)).unwrap()
+    });
+    let bytecode = time("bytecode", 1, || {
+        compiled.eval(black_box(&bindings)).unwrap()
+    });
+    println!("speedup        {:>8.2}x", walker / bytecode);
+
+    let column: Vec<f32> = (1..=ROWS).map(|row| row as f32 / 100.0).collect();
+    let mut out = vec![0.0; column.len()];
+    let batch = time("batch", ROWS, || {
+        compiled
+            .eval_batch(&[black_box(&column)], &mut out)
+            .unwrap();
+        out[0]
+    });
+    println!("speedup        {:>8.2}x", walker / batch);
+
+    #[cfg(feature = "jit")]
+    {
+        let jit = compiled.jit();
+        let wide = [2.5f64];
+        let expected = f64::from(compiled.eval(&bindings).unwrap());
+        assert!((expected - jit.eval(&wide).unwrap()).abs() <= expected.abs() * 1e-6);
+
+        let native = time("jit", 1, || jit.eval(black_box(&wide)).unwrap());
+        println!("speedup        {:>8.2}x", walker / native);
+    }
+}
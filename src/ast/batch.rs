@@ -0,0 +1,196 @@
+#![allow(clippy::needless_return)]
+
+use alloc::{string::ToString, vec};
+use core::ops::Range;
+
+use super::{
+    bytecode::Instruction,
+    compiled::CompiledExpr,
+    evaluator::Evaluation,
+    math,
+    parser::{FloatPolicy, ParseError},
+};
+
+/// Rows evaluated together, so every instruction runs as one loop over a
+/// column of the stack that the compiler can vectorise.
+const CHUNK: usize = 256;
+
+impl CompiledExpr {
+    /// Evaluates every row into `out`, with `columns[i][row]` as the value of
+    /// `variables()[i]`. Each column needs a value per row of `out`.
+    ///
+    /// Results are the same as calling `eval` row by row, which is what
+    /// happens for any chunk of rows where the float policy has to step in.
+    /// Fails with the error of the first failing row, leaving the rows after
+    /// it unspecified. Warnings are discarded.
+    pub fn eval_batch(&self, columns: &[&[f32]], out: &mut [f32]) -> Result<(), ParseError> {
+        self.check_columns(columns, out.len())?;
+
+        let program = self.program();
+        let mut stack = vec![0.0; (program.max_stack() + program.temporaries()) * CHUNK];
+        let mut bindings = vec![0.0; self.variables().len()];
+
+        for (index, out) in out.chunks_mut(CHUNK).enumerate() {
+            let rows = index * CHUNK..index * CHUNK + out.len();
+
+            if self.run_chunk(columns, rows.clone(), &mut stack, out)
+                || self.float_policy() == FloatPolicy::Ieee
+            {
+                continue;
+            }
+
+            for (row, value) in rows.zip(out.iter_mut()) {
+                for (binding, column) in bindings.iter_mut().zip(columns) {
+                    *binding = column[row];
+                }
+
+                *value = self.eval(&bindings)?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// Like `eval_batch`, splitting the rows between up to `threads` threads.
+    #[cfg(feature = "std")]
+    pub fn eval_batch_parallel(
+        &self,
+        columns: &[&[f32]],
+        out: &mut [f32],
+        threads: usize,
+    ) -> Result<(), ParseError> {
+        self.check_columns(columns, out.len())?;
+
+        let columns = &columns[..self.variables().len()];
+        let rows_per_thread = out.len().div_ceil(threads.max(1)).max(CHUNK);
+
+        return std::thread::scope(|scope| {
+            let handles: Vec<_> = out
+                .chunks_mut(rows_per_thread)
+                .enumerate()
+                .map(|(index, out)| {
+                    let rows = index * rows_per_thread..index * rows_per_thread + out.len();
+                    let columns: Vec<&[f32]> =
+                        columns.iter().map(|column| &column[rows.clone()]).collect();
+
+                    scope.spawn(move || self.eval_batch(&columns, out))
+                })
+                .collect();
+
+            // joined in row order, so the error is the one of the first failing row
+            return handles.into_iter().try_for_each(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            });
+        });
+    }
+
+    fn check_columns(&self, columns: &[&[f32]], rows: usize) -> Result<(), ParseError> {
+        if let Some(unbound) = self.variables().get(columns.len()) {
+            return Err(ParseError::UnboundVariable(unbound.to_string()));
+        }
+
+        for (variable, column) in self.variables().iter().zip(columns) {
+            if column.len() != rows {
+                return Err(ParseError::ColumnLength(
+                    variable.to_string(),
+                    column.len(),
+                    rows,
+                ));
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// Runs the program over `rows` with one stack column of `CHUNK` values
+    /// per stack slot, followed by one per temporary. Returns false if any
+    /// value in the chunk needs the float policy, in which case `out` is
+    /// left unspecified.
+    fn run_chunk(
+        &self,
+        columns: &[&[f32]],
+        rows: Range<usize>,
+        stack: &mut [f32],
+        out: &mut [f32],
+    ) -> bool {
+        let checked = self.float_policy() != FloatPolicy::Ieee;
+        let temporaries = self.program().max_stack() * CHUNK;
+        let n = rows.len();
+        let mut finite = true;
+        let mut len = 0;
+
+        for instruction in self.program().instructions() {
+            match *instruction {
+                Instruction::Const(value) => {
+                    stack[len * CHUNK..][..n].fill(value);
+                    len += 1;
+                }
+                Instruction::Load(slot) => {
+                    stack[len * CHUNK..][..n]
+                        .copy_from_slice(&columns[slot as usize][rows.clone()]);
+                    len += 1;
+                }
+                Instruction::Save(temporary) => {
+                    let temporary = temporaries + temporary as usize * CHUNK;
+                    stack.copy_within((len - 1) * CHUNK..(len - 1) * CHUNK + n, temporary);
+                }
+                Instruction::Recall(temporary) => {
+                    let temporary = temporaries + temporary as usize * CHUNK;
+                    stack.copy_within(temporary..temporary + n, len * CHUNK);
+                    len += 1;
+                }
+                Instruction::Sin | Instruction::Cos | Instruction::Tan | Instruction::Log => {
+                    let function: fn(f32) -> f32 = match instruction {
+                        Instruction::Sin => math::sin,
+                        Instruction::Cos => math::cos,
+                        Instruction::Tan => math::tan,
+                        _ => math::log10,
+                    };
+                    let kind = instruction.kind();
+
+                    for arg in &mut stack[(len - 1) * CHUNK..][..n] {
+                        let value = function(*arg);
+
+                        if checked {
+                            finite &= Evaluation::in_domain(&kind, *arg) && value.is_finite();
+                        }
+
+                        *arg = value;
+                    }
+                }
+                binary => {
+                    let (below, top) = stack.split_at_mut((len - 1) * CHUNK);
+                    let rhs = &mut below[(len - 2) * CHUNK..][..n];
+                    let lhs = &top[..n];
+
+                    let pairs = rhs.iter_mut().zip(lhs);
+                    match binary {
+                        Instruction::Add => pairs.for_each(|(rhs, lhs)| *rhs += lhs),
+                        Instruction::Sub => pairs.for_each(|(rhs, lhs)| *rhs -= lhs),
+                        Instruction::Mul => pairs.for_each(|(rhs, lhs)| *rhs *= lhs),
+                        Instruction::Div => pairs.for_each(|(rhs, lhs)| *rhs /= lhs),
+                        _ => pairs.for_each(|(rhs, lhs)| *rhs = math::powf(*rhs, *lhs)),
+                    }
+
+                    if checked {
+                        finite &= rhs
+                            .iter()
+                            .fold(true, |finite, value| finite & value.is_finite());
+                    }
+
+                    len -= 1;
+                }
+            }
+        }
+
+        if len == 0 {
+            out.fill(0.0);
+        } else {
+            out.copy_from_slice(&stack[(len - 1) * CHUNK..][..n]);
+        }
+
+        return finite;
+    }
+}
@@ -0,0 +1,65 @@
+#![allow(clippy::needless_return)]
+
+use alloc::string::ToString;
+use num_bigint::{BigInt, Sign};
+
+use super::{lexer::TokenKind, number::Number, parser::ParseError};
+
+/// Bits a power may grow to, past which it would take too long to compute.
+const MAX_POWER_BITS: u64 = 1 << 20;
+
+/// Whole numbers of any size. Division has to come out even, and `pi` and
+/// the functions have no whole results.
+impl Number for BigInt {
+    fn parse_literal(literal: &str) -> Option<Self> {
+        return literal.parse().ok();
+    }
+
+    fn from_f32(value: f32) -> Option<Self> {
+        if !value.is_finite() || value % 1.0 != 0.0 {
+            return None;
+        }
+
+        // written without a fraction or exponent, as the value is whole
+        return value.to_string().parse().ok();
+    }
+
+    fn pi() -> Option<Self> {
+        return None;
+    }
+
+    fn binary(&self, operator: &TokenKind, rhs: &Self) -> Result<Self, ParseError> {
+        match operator {
+            TokenKind::Plus => return Ok(self + rhs),
+            TokenKind::Minus => return Ok(self - rhs),
+            TokenKind::Asterix => return Ok(self * rhs),
+            TokenKind::Slash => {
+                if rhs.sign() == Sign::NoSign {
+                    return Err(ParseError::DivisionByZero);
+                }
+
+                let quotient = self / rhs;
+                if &quotient * rhs != *self {
+                    return Err(ParseError::Unsupported(self.written(operator, rhs)));
+                }
+
+                return Ok(quotient);
+            }
+            TokenKind::Power => {
+                let unsupported = || ParseError::Unsupported(self.written(operator, rhs));
+                let exponent = u32::try_from(rhs).map_err(|_| unsupported())?;
+
+                if self.bits().saturating_mul(u64::from(exponent)) > MAX_POWER_BITS {
+                    return Err(ParseError::NonFinite(self.written(operator, rhs)));
+                }
+
+                return Ok(self.pow(exponent));
+            }
+            unknown => return Err(ParseError::UnexpectedOperator(unknown.clone())),
+        }
+    }
+
+    fn apply(&self, function: &TokenKind) -> Result<Self, ParseError> {
+        return Err(ParseError::Unsupported(self.applied(function)));
+    }
+}
@@ -0,0 +1,258 @@
+#![allow(clippy::needless_return)]
+
+use alloc::{format, string::String, vec, vec::Vec};
+use core::f32::consts::PI;
+
+use super::{
+    evaluator::{Evaluation, INLINE_STACK},
+    lexer::{Token, TokenKind},
+    math,
+    parser::ParseError,
+    trace::{Level, Stage},
+};
+
+/// One step of a `Program`. Operands are taken from the top of the stack,
+/// the right-hand one being on top.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instruction {
+    Const(f32),
+    /// Pushes the binding in the given variable slot.
+    Load(u32),
+    /// Copies the top of the stack into a temporary, leaving it in place.
+    Save(u32),
+    /// Pushes the value of a temporary written by an earlier `Save`.
+    Recall(u32),
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Sin,
+    Cos,
+    Tan,
+    Log,
+}
+
+impl Instruction {
+    /// The token this instruction was compiled from, used for messages.
+    pub fn kind(&self) -> TokenKind {
+        match self {
+            Instruction::Const(value) => TokenKind::Number(*value),
+            Instruction::Load(_) | Instruction::Save(_) | Instruction::Recall(_) => {
+                TokenKind::Identifier(String::new())
+            }
+            Instruction::Add => TokenKind::Plus,
+            Instruction::Sub => TokenKind::Minus,
+            Instruction::Mul => TokenKind::Asterix,
+            Instruction::Div => TokenKind::Slash,
+            Instruction::Pow => TokenKind::Power,
+            Instruction::Sin => TokenKind::Sin,
+            Instruction::Cos => TokenKind::Cos,
+            Instruction::Tan => TokenKind::Tan,
+            Instruction::Log => TokenKind::Log,
+        }
+    }
+
+    /// How many values the instruction pops.
+    pub fn arity(&self) -> usize {
+        match self {
+            Instruction::Const(_) | Instruction::Load(_) | Instruction::Recall(_) => 0,
+            Instruction::Save(_) => 1,
+            Instruction::Sin | Instruction::Cos | Instruction::Tan | Instruction::Log => 1,
+            _ => 2,
+        }
+    }
+}
+
+/// Stack bytecode for an expression. Variable slots and the stack depth are
+/// resolved when compiling, so `run` never has to look up a name or check for
+/// a missing operand.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    instructions: Vec<Instruction>,
+    max_stack: usize,
+    temporaries: usize,
+}
+
+impl Program {
+    /// Fails if an instruction is missing an operand or recalls a temporary
+    /// that was never saved.
+    pub fn new(instructions: Vec<Instruction>) -> Result<Program, ParseError> {
+        let mut depth = 0usize;
+        let mut max_stack = 0usize;
+        let mut saved: Vec<bool> = Vec::new();
+
+        for instruction in &instructions {
+            let arity = instruction.arity();
+
+            if depth < arity {
+                return Err(ParseError::MissingExpression);
+            }
+
+            match *instruction {
+                Instruction::Save(temporary) => {
+                    let temporary = temporary as usize;
+                    if saved.len() <= temporary {
+                        saved.resize(temporary + 1, false);
+                    }
+                    saved[temporary] = true;
+                }
+                Instruction::Recall(temporary)
+                    if !saved.get(temporary as usize).copied().unwrap_or(false) =>
+                {
+                    return Err(ParseError::MissingExpression);
+                }
+                _ => {}
+            }
+
+            depth = depth - arity + 1;
+            max_stack = max_stack.max(depth);
+        }
+
+        return Ok(Program {
+            instructions,
+            max_stack,
+            temporaries: saved.len(),
+        });
+    }
+
+    /// Compiles tokens in evaluation order. Identifiers become slots indexed
+    /// by their position in `variables`.
+    pub fn compile<'t, I>(tokens: I, variables: &[String]) -> Result<Program, ParseError>
+    where
+        I: Iterator<Item = &'t Token>,
+    {
+        let mut instructions = Vec::new();
+
+        for token in tokens {
+            let instruction = match token.kind() {
+                TokenKind::Number(value) => Instruction::Const(*value),
+                TokenKind::Pi => Instruction::Const(PI),
+                TokenKind::Identifier(ident) => {
+                    let slot = variables
+                        .iter()
+                        .position(|variable| variable == ident)
+                        .ok_or_else(|| ParseError::UnboundVariable(ident.clone()))?;
+
+                    Instruction::Load(slot as u32)
+                }
+                TokenKind::Plus => Instruction::Add,
+                TokenKind::Minus => Instruction::Sub,
+                TokenKind::Asterix => Instruction::Mul,
+                TokenKind::Slash => Instruction::Div,
+                TokenKind::Power => Instruction::Pow,
+                TokenKind::Sin => Instruction::Sin,
+                TokenKind::Cos => Instruction::Cos,
+                TokenKind::Tan => Instruction::Tan,
+                TokenKind::Log => Instruction::Log,
+                unknown => return Err(ParseError::UnexpectedOperator(unknown.clone())),
+            };
+
+            instructions.push(instruction);
+        }
+
+        return Program::new(instructions);
+    }
+
+    pub fn instructions(&self) -> &[Instruction] {
+        return &self.instructions;
+    }
+
+    /// The most values on the stack at any point while running.
+    pub fn max_stack(&self) -> usize {
+        return self.max_stack;
+    }
+
+    /// How many values `Save` and `Recall` address.
+    pub fn temporaries(&self) -> usize {
+        return self.temporaries;
+    }
+
+    /// Runs the program with `bindings[slot]` for each `Load(slot)`. The
+    /// caller makes sure there is a binding for every slot.
+    pub fn run(&self, bindings: &[f32], evaluation: &mut Evaluation) -> Result<f32, ParseError> {
+        let mut inline = [0.0f32; INLINE_STACK];
+        let mut heap;
+
+        // temporaries live above the deepest the stack gets
+        let size = self.max_stack + self.temporaries;
+        let stack: &mut [f32] = if size <= INLINE_STACK {
+            &mut inline
+        } else {
+            heap = vec![0.0; size];
+            &mut heap
+        };
+
+        let tracing = evaluation.tracer.enabled(Level::Trace);
+        let mut len = 0;
+
+        for (step, instruction) in self.instructions.iter().enumerate() {
+            evaluation.budget.check(step)?;
+
+            match *instruction {
+                Instruction::Const(value) => {
+                    stack[len] = value;
+                    len += 1;
+                }
+                Instruction::Load(slot) => {
+                    stack[len] = bindings[slot as usize];
+                    len += 1;
+                }
+                Instruction::Save(temporary) => {
+                    stack[self.max_stack + temporary as usize] = stack[len - 1];
+                }
+                Instruction::Recall(temporary) => {
+                    stack[len] = stack[self.max_stack + temporary as usize];
+                    len += 1;
+                }
+                Instruction::Sin | Instruction::Cos | Instruction::Tan | Instruction::Log => {
+                    let arg = stack[len - 1];
+                    let value = evaluation.apply_function(&instruction.kind(), arg)?;
+
+                    if tracing {
+                        evaluation.tracer.emit(Level::Trace, Stage::Evaluator, || {
+                            format!("{}{arg:?} = {value:?}", instruction.kind())
+                        });
+                    }
+
+                    stack[len - 1] = value;
+                }
+                binary => {
+                    let lhs = stack[len - 1];
+                    let rhs = stack[len - 2];
+
+                    let mut value = match binary {
+                        Instruction::Add => rhs + lhs,
+                        Instruction::Sub => rhs - lhs,
+                        Instruction::Mul => rhs * lhs,
+                        Instruction::Div => rhs / lhs,
+                        _ => math::powf(rhs, lhs),
+                    };
+
+                    // division by zero never yields a finite value, so this
+                    // one comparison covers every check on the fast path
+                    if !value.is_finite() {
+                        value = evaluation.check_binary(&binary.kind(), lhs, rhs, value)?;
+                    }
+
+                    if tracing {
+                        evaluation.tracer.emit(Level::Trace, Stage::Evaluator, || {
+                            format!("{rhs:?}{}{lhs:?} = {value:?}", binary.kind())
+                        });
+                    }
+
+                    len -= 1;
+                    stack[len - 1] = value;
+                }
+            }
+        }
+
+        let result = if len == 0 { 0.0 } else { stack[len - 1] };
+
+        evaluation.tracer.emit(Level::Debug, Stage::Evaluator, || {
+            format!("result: {result:?}")
+        });
+
+        return Ok(result);
+    }
+}
@@ -0,0 +1,139 @@
+#![allow(clippy::needless_return)]
+
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+
+use super::compiled::CompiledExpr;
+
+/// Counters reported by `Interpreter::cache_stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub len: usize,
+    pub capacity: usize,
+}
+
+#[derive(Debug)]
+struct Entry {
+    compiled: CompiledExpr,
+    last_used: u64,
+}
+
+/// Least recently used cache of compiled expressions, keyed by their source
+/// with whitespace normalised. A capacity of 0 disables it.
+#[derive(Debug, Default)]
+pub struct ExpressionCache {
+    entries: BTreeMap<String, Entry>,
+    capacity: usize,
+    clock: u64,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+impl ExpressionCache {
+    pub fn new(capacity: usize) -> Self {
+        return Self {
+            capacity,
+            ..Self::default()
+        };
+    }
+
+    /// Trims and collapses every run of whitespace within a line into one
+    /// space, and leaves out blank lines. Line breaks are kept, as they
+    /// separate statements.
+    pub fn normalize(expression: &str) -> String {
+        return expression
+            .lines()
+            .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+
+    pub fn capacity(&self) -> usize {
+        return self.capacity;
+    }
+
+    /// Evicts the least recently used entries until at most `capacity` remain.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+
+        while self.entries.len() > capacity {
+            self.evict();
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        return CacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+            len: self.entries.len(),
+            capacity: self.capacity,
+        };
+    }
+
+    /// Counts a hit or a miss for `key`, a normalised source.
+    pub fn get(&mut self, key: &str) -> Option<&CompiledExpr> {
+        self.clock += 1;
+
+        let Some(entry) = self.entries.get_mut(key) else {
+            self.misses += 1;
+            return None;
+        };
+
+        self.hits += 1;
+        entry.last_used = self.clock;
+
+        return Some(&entry.compiled);
+    }
+
+    pub fn insert(&mut self, key: String, compiled: CompiledExpr) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.capacity {
+            self.evict();
+        }
+
+        self.clock += 1;
+        self.entries.insert(
+            key,
+            Entry {
+                compiled,
+                last_used: self.clock,
+            },
+        );
+    }
+
+    /// Drops every entry that refers to `name`, returning how many there were.
+    pub fn invalidate(&mut self, name: &str) -> usize {
+        let before = self.entries.len();
+
+        self.entries
+            .retain(|_, entry| !entry.compiled.variables().iter().any(|v| v == name));
+
+        return before - self.entries.len();
+    }
+
+    /// Drops every entry but keeps the counters.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn evict(&mut self) {
+        let oldest = self
+            .entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.last_used)
+            .map(|(key, _)| key.clone());
+
+        if let Some(key) = oldest {
+            self.entries.remove(&key);
+            self.evictions += 1;
+        }
+    }
+}
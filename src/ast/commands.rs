@@ -0,0 +1,269 @@
+#![allow(clippy::needless_return)]
+
+use std::{fmt::Write, fs};
+
+use super::{
+    console_error::ConsoleError,
+    expr::{Expr, ExprKind},
+    interpreter::Interpreter,
+    lexer::Lexer,
+    parser::{AngleUnit, FloatPolicy},
+    registry::{self, FUNCTIONS},
+};
+
+/// Console commands with their usage and a one-line description.
+pub const COMMANDS: &[(&str, &str, &str)] = &[
+    (
+        ":help",
+        ":help [name]",
+        "Describe a function, constant or variable, or list the commands",
+    ),
+    (":vars", ":vars", "List the variables and recorded results"),
+    (":funcs", ":funcs", "List the built-in and user functions"),
+    (":tokens", ":tokens expr", "Show the tokens the lexer reads"),
+    (":ast", ":ast expr", "Show the tree the parser builds"),
+    (
+        ":mode",
+        ":mode [strict|ieee|warn] [rad|deg]",
+        "Show or set the float policy and angle unit",
+    ),
+    (
+        ":format",
+        ":format [auto|fixed|sci|eng|si] [digits|.decimals] [group|nogroup] [smart|exact]",
+        "Show or set how results are written, also per result with `expr as sci`",
+    ),
+    (
+        ":save",
+        ":save file",
+        "Write the variables, functions, modes and results to a file",
+    ),
+    (
+        ":load",
+        ":load file",
+        "Replace the session with one saved to a file",
+    ),
+    (":clear", ":clear", "Clear the screen"),
+    (
+        ":reset",
+        ":reset",
+        "Forget all variables and functions and go back to the default modes",
+    ),
+];
+
+/// Whether `line` is a console command rather than an expression.
+pub fn is_command(line: &str) -> bool {
+    return line.trim_start().starts_with(':');
+}
+
+fn usage(command: &str) -> ConsoleError {
+    let usage = COMMANDS
+        .iter()
+        .find(|(name, ..)| *name == command)
+        .map_or(command, |(_, usage, _)| *usage);
+
+    return ConsoleError::CommandUsage(usage.to_string());
+}
+
+/// Runs a line starting with `:`, returning what to print. `:clear` is left
+/// to the caller, since only a terminal can be cleared.
+pub fn run(interpreter: &mut Interpreter, line: &str) -> Result<String, ConsoleError> {
+    let line = line.trim();
+    let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+    let argument = argument.trim();
+
+    match command {
+        ":help" => return help(interpreter, argument),
+        ":vars" => return Ok(vars(interpreter)),
+        ":funcs" => return Ok(funcs(interpreter)),
+        ":tokens" if !argument.is_empty() => return tokens(argument),
+        ":ast" if !argument.is_empty() => return Ok(ast(interpreter, argument)),
+        ":mode" => return mode(interpreter, argument),
+        ":format" => return format(interpreter, argument),
+        ":clear" => return Ok(String::new()),
+        ":reset" => {
+            interpreter.reset();
+            return Ok(String::new());
+        }
+        ":save" if !argument.is_empty() => return save(interpreter, argument),
+        ":load" if !argument.is_empty() => return load(interpreter, argument),
+        ":tokens" | ":ast" | ":save" | ":load" => return Err(usage(command)),
+        unknown => return Err(ConsoleError::UnknownCommand(unknown.to_string())),
+    }
+}
+
+/// The commands, or what `name` refers to.
+fn help(interpreter: &Interpreter, name: &str) -> Result<String, ConsoleError> {
+    if name.is_empty() {
+        let commands: Vec<String> = COMMANDS
+            .iter()
+            .map(|(_, usage, doc)| format!("{usage:<12} {doc}"))
+            .collect();
+
+        return Ok(commands.join("\n"));
+    }
+
+    if let Some((signature, doc)) = registry::describe(name) {
+        return Ok(format!("{signature:<12} {doc}"));
+    }
+
+    if let Some((_, usage, doc)) = COMMANDS.iter().find(|(command, ..)| *command == name) {
+        return Ok(format!("{usage:<12} {doc}"));
+    }
+
+    if let Some(function) = interpreter.function(name) {
+        return Ok(function.to_string());
+    }
+
+    let value = interpreter.registry().resolve(name)?;
+
+    return Ok(format!("{name:<12} = {value:?}"));
+}
+
+fn vars(interpreter: &Interpreter) -> String {
+    let results = interpreter
+        .results()
+        .iter()
+        .enumerate()
+        .map(|(index, value)| (format!("${}", index + 1), *value));
+    let variables: Vec<String> = interpreter
+        .registry()
+        .variables()
+        .map(|(name, value)| (name.to_string(), value))
+        .chain(results)
+        .map(|(name, value)| format!("{name:<12} = {value:?}"))
+        .collect();
+
+    if variables.is_empty() {
+        return String::from("No variables defined");
+    }
+
+    return variables.join("\n");
+}
+
+fn funcs(interpreter: &Interpreter) -> String {
+    let functions: Vec<String> = FUNCTIONS
+        .iter()
+        .filter_map(|(name, _)| registry::describe(name))
+        .map(|(signature, doc)| format!("{signature:<12} {doc}"))
+        .chain(interpreter.registry().functions().map(ToString::to_string))
+        .collect();
+
+    return functions.join("\n");
+}
+
+fn tokens(expression: &str) -> Result<String, ConsoleError> {
+    let (tokens, diagnostics) = Lexer::new().lex_all(expression);
+    let mut output = String::new();
+
+    for token in &tokens {
+        let span = token.span();
+        let _ = writeln!(
+            output,
+            "{:<12} {}..{}",
+            format!("{:?}", token.kind()),
+            span.start,
+            span.end
+        );
+    }
+
+    if let Some(diagnostic) = diagnostics.into_iter().next() {
+        return Err(diagnostic.into_error().into());
+    }
+
+    return Ok(output.trim_end().to_string());
+}
+
+fn label(kind: &ExprKind) -> String {
+    match kind {
+        ExprKind::Number(value) => return format!("{value:?}"),
+        ExprKind::Pi => return String::from("pi"),
+        ExprKind::Identifier(name) => return name.clone(),
+        ExprKind::Call(function, _) => return function.to_string().trim().to_lowercase(),
+        ExprKind::Binary(operator, ..) => return operator.to_string().trim().to_string(),
+        ExprKind::Error => return String::from("<error>"),
+    }
+}
+
+/// One node per line, children indented below their parent, followed by
+/// every problem found.
+fn ast(interpreter: &mut Interpreter, expression: &str) -> String {
+    let (tree, diagnostics) = interpreter.diagnose(expression);
+    let mut output = String::new();
+    // walked with an explicit stack, a long chain is as deep as it is long
+    let mut pending: Vec<(&Expr, usize)> = tree.iter().map(|tree| (tree, 0)).collect();
+
+    while let Some((expr, depth)) = pending.pop() {
+        let span = expr.span();
+        let _ = writeln!(
+            output,
+            "{:indent$}{} {}..{}",
+            "",
+            label(expr.kind()),
+            span.start,
+            span.end,
+            indent = depth * 2
+        );
+
+        match expr.kind() {
+            ExprKind::Call(_, arg) => pending.push((arg, depth + 1)),
+            ExprKind::Binary(_, lhs, rhs) => {
+                pending.extend([(&**rhs, depth + 1), (&**lhs, depth + 1)])
+            }
+            _ => {}
+        }
+    }
+
+    for diagnostic in &diagnostics {
+        let span = diagnostic.span();
+        let message = diagnostic.error().message();
+        let _ = writeln!(output, "Error at {}..{}: {message}", span.start, span.end);
+    }
+
+    return output.trim_end().to_string();
+}
+
+fn file_access(path: &str, err: std::io::Error) -> ConsoleError {
+    return ConsoleError::FileAccess(path.to_string(), err.to_string());
+}
+
+fn save(interpreter: &Interpreter, path: &str) -> Result<String, ConsoleError> {
+    fs::write(path, interpreter.save_session()).map_err(|err| file_access(path, err))?;
+
+    return Ok(format!("Saved the session to `{path}`"));
+}
+
+fn load(interpreter: &mut Interpreter, path: &str) -> Result<String, ConsoleError> {
+    let session = fs::read_to_string(path).map_err(|err| file_access(path, err))?;
+    interpreter.load_session(&session)?;
+
+    return Ok(format!("Loaded the session from `{path}`"));
+}
+
+fn mode(interpreter: &mut Interpreter, argument: &str) -> Result<String, ConsoleError> {
+    for setting in argument.split_whitespace() {
+        if let Some(policy) = FloatPolicy::from_name(setting) {
+            interpreter.set_float_policy(policy);
+        } else if let Some(unit) = AngleUnit::from_name(setting) {
+            interpreter.set_angle_unit(unit);
+        } else {
+            return Err(usage(":mode"));
+        }
+    }
+
+    return Ok(format!(
+        "float policy: {}, angle unit: {}",
+        interpreter.float_policy().name(),
+        interpreter.angle_unit().name()
+    ));
+}
+
+fn format(interpreter: &mut Interpreter, argument: &str) -> Result<String, ConsoleError> {
+    let mut number_format = interpreter.number_format();
+
+    for setting in argument.split_whitespace() {
+        number_format.set(setting).map_err(|_| usage(":format"))?;
+    }
+    interpreter.set_number_format(number_format);
+
+    return Ok(format!("number format: {number_format}"));
+}
@@ -0,0 +1,122 @@
+#![allow(clippy::needless_return)]
+
+use alloc::{
+    collections::VecDeque,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use super::{
+    bytecode::Program,
+    evaluator::Evaluation,
+    lexer::{Token, TokenKind},
+    limits::Budget,
+    parser::{FloatPolicy, ParseError},
+    trace::Tracer,
+};
+
+/// An expression lexed and parsed once by `Interpreter::compile`.
+///
+/// Every identifier in it is a variable whose value is passed to `eval`, in
+/// the order given by `variables`. Evaluating does not allocate unless a
+/// warning is recorded or an error is returned, and a `CompiledExpr` can be
+/// shared between threads.
+#[derive(Debug, Clone)]
+pub struct CompiledExpr {
+    source: String,
+    /// Tokens in evaluation order (reverse polish notation).
+    tokens: Vec<Token>,
+    variables: Vec<String>,
+    program: Program,
+    float_policy: FloatPolicy,
+}
+
+impl CompiledExpr {
+    /// Fails if an operator in `queue` is missing an operand.
+    pub fn new(
+        source: &str,
+        queue: VecDeque<Token>,
+        float_policy: FloatPolicy,
+    ) -> Result<Self, ParseError> {
+        let tokens: Vec<Token> = queue.into_iter().rev().collect();
+        let mut variables: Vec<String> = Vec::new();
+
+        for token in &tokens {
+            if let TokenKind::Identifier(ident) = token.kind() {
+                if !variables.contains(ident) {
+                    variables.push(ident.clone());
+                }
+            }
+        }
+
+        let program = Program::compile(tokens.iter(), &variables)?;
+
+        return Ok(Self {
+            source: source.to_string(),
+            tokens,
+            variables,
+            program,
+            float_policy,
+        });
+    }
+
+    pub fn source(&self) -> &str {
+        return &self.source;
+    }
+
+    /// The tokens in the order they are evaluated (reverse polish notation).
+    pub fn tokens(&self) -> impl Iterator<Item = &Token> {
+        return self.tokens.iter();
+    }
+
+    /// Names of the variables `eval` expects values for, in order.
+    pub fn variables(&self) -> &[String] {
+        return &self.variables;
+    }
+
+    /// Position of `name` in the bindings passed to `eval`.
+    pub fn slot(&self, name: &str) -> Option<usize> {
+        return self.variables.iter().position(|variable| variable == name);
+    }
+
+    pub fn program(&self) -> &Program {
+        return &self.program;
+    }
+
+    pub fn float_policy(&self) -> FloatPolicy {
+        return self.float_policy;
+    }
+
+    /// Replaces the bytecode with `Program::optimize` of it.
+    pub fn optimized(mut self) -> Self {
+        self.program = self.program.optimize(self.float_policy);
+
+        return self;
+    }
+
+    /// Evaluates with `bindings[i]` as the value of `variables()[i]`.
+    /// Warnings under `FloatPolicy::Warn` are discarded.
+    pub fn eval(&self, bindings: &[f32]) -> Result<f32, ParseError> {
+        return self.eval_with_warnings(bindings, &mut Vec::new());
+    }
+
+    pub fn eval_with_warnings(
+        &self,
+        bindings: &[f32],
+        warnings: &mut Vec<ParseError>,
+    ) -> Result<f32, ParseError> {
+        if let Some(unbound) = self.variables.get(bindings.len()) {
+            return Err(ParseError::UnboundVariable(unbound.to_string()));
+        }
+
+        let tracer = Tracer::default();
+        let mut evaluation = Evaluation {
+            float_policy: self.float_policy,
+            warnings,
+            tracer: &tracer,
+            budget: Budget::default(),
+        };
+
+        return self.program.run(bindings, &mut evaluation);
+    }
+}
@@ -0,0 +1,70 @@
+#![allow(clippy::needless_return)]
+
+use alloc::string::String;
+use core::f64::consts::PI;
+use num_complex::Complex64;
+
+use super::{lexer::TokenKind, number::Number, parser::ParseError};
+
+fn finite(value: Complex64, operation: impl FnOnce() -> String) -> Result<Complex64, ParseError> {
+    if !value.is_finite() {
+        return Err(ParseError::NonFinite(operation()));
+    }
+
+    return Ok(value);
+}
+
+/// Complex numbers of two `f64`, with `i` for the imaginary unit. Powers
+/// and `log` take the principal value.
+impl Number for Complex64 {
+    fn parse_literal(literal: &str) -> Option<Self> {
+        return literal.parse().ok().map(|re| Complex64::new(re, 0.0));
+    }
+
+    fn from_f32(value: f32) -> Option<Self> {
+        return Some(Complex64::new(f64::from(value), 0.0));
+    }
+
+    fn named(name: &str) -> Option<Self> {
+        return (name == "i").then(Complex64::i);
+    }
+
+    fn pi() -> Option<Self> {
+        return Some(Complex64::new(PI, 0.0));
+    }
+
+    fn binary(&self, operator: &TokenKind, rhs: &Self) -> Result<Self, ParseError> {
+        let (lhs, rhs) = (*self, *rhs);
+        let whole = rhs.im == 0.0 && rhs.re % 1.0 == 0.0 && rhs.re.abs() <= f64::from(i32::MAX);
+
+        let value = match operator {
+            TokenKind::Plus => lhs + rhs,
+            TokenKind::Minus => lhs - rhs,
+            TokenKind::Asterix => lhs * rhs,
+            TokenKind::Slash if rhs == Complex64::ZERO => return Err(ParseError::DivisionByZero),
+            TokenKind::Slash => lhs / rhs,
+            // exact for whole exponents, so `i ^ 2` is `-1`
+            TokenKind::Power if whole => lhs.powi(rhs.re as i32),
+            TokenKind::Power if lhs == Complex64::ZERO && rhs.re > 0.0 => Complex64::ZERO,
+            TokenKind::Power => lhs.powc(rhs),
+            unknown => return Err(ParseError::UnexpectedOperator(unknown.clone())),
+        };
+
+        return finite(value, || self.written(operator, &rhs));
+    }
+
+    fn apply(&self, function: &TokenKind) -> Result<Self, ParseError> {
+        let value = match function {
+            TokenKind::Sin => self.sin(),
+            TokenKind::Cos => self.cos(),
+            TokenKind::Tan => self.tan(),
+            TokenKind::Log if *self == Complex64::ZERO => {
+                return Err(ParseError::OutOfDomain(self.applied(function)));
+            }
+            TokenKind::Log => self.log10(),
+            unknown => return Err(ParseError::UnexpectedOperator(unknown.clone())),
+        };
+
+        return finite(value, || self.applied(function));
+    }
+}
@@ -0,0 +1,184 @@
+#![allow(clippy::needless_return)]
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    console_error::ConsoleError,
+    format::NumberFormat,
+    interpreter::Interpreter,
+    parser::{AngleUnit, FloatPolicy},
+};
+
+/// Prompt with `{n}` standing for the number the next result is recorded as.
+const DEFAULT_PROMPT: &str = "${n} >>> ";
+
+/// Settings for the console binary, read from `key = value` lines:
+///
+/// ```text
+/// # float policy and angle unit, as for `:mode`
+/// mode = warn
+/// angle = deg
+/// # significant digits results are shown with
+/// precision = 6
+/// # or any of the settings of `:format`
+/// format = eng 4 group
+/// color = off
+/// prompt = "calc {n}> "
+/// # statements run before the first prompt, relative to this file
+/// prelude = constants.arth
+/// ```
+///
+/// Keys left out keep their defaults, and blank lines and lines starting
+/// with `#` are skipped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub float_policy: FloatPolicy,
+    pub angle_unit: AngleUnit,
+    pub number_format: NumberFormat,
+    pub color: bool,
+    /// `{n}` is replaced with the number of the next result.
+    pub prompt: String,
+    pub prelude: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        return Self {
+            float_policy: FloatPolicy::default(),
+            angle_unit: AngleUnit::default(),
+            number_format: NumberFormat::default(),
+            color: true,
+            prompt: DEFAULT_PROMPT.to_string(),
+            prelude: None,
+        };
+    }
+}
+
+fn file_access(path: &Path, err: io::Error) -> ConsoleError {
+    return ConsoleError::FileAccess(path.display().to_string(), err.to_string());
+}
+
+fn flag(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "on" | "true" | "yes" => return Some(true),
+        "off" | "false" | "no" => return Some(false),
+        _ => return None,
+    }
+}
+
+impl Config {
+    /// The `arth_lexer` directory in the user's config directory, which
+    /// also holds the console history.
+    pub fn dir() -> Option<PathBuf> {
+        return dirs::config_dir().map(|dir| dir.join("arth_lexer"));
+    }
+
+    /// Reads `config` from `Config::dir`. Without a config file the
+    /// defaults are used, along with a `prelude` file next to it if there
+    /// is one.
+    pub fn load() -> Result<Self, ConsoleError> {
+        let Some(dir) = Self::dir() else {
+            return Ok(Self::default());
+        };
+
+        let path = dir.join("config");
+        let mut config = match path.exists() {
+            true => Self::read(&path)?,
+            false => Self::default(),
+        };
+
+        if config.prelude.is_none() {
+            let prelude = dir.join("prelude");
+            config.prelude = prelude.exists().then_some(prelude);
+        }
+
+        return Ok(config);
+    }
+
+    /// Reads the config file at `path`, resolving a relative prelude path
+    /// against the directory the file is in.
+    pub fn read(path: &Path) -> Result<Self, ConsoleError> {
+        let text = fs::read_to_string(path).map_err(|err| file_access(path, err))?;
+        let mut config = Self::parse(&text)?;
+
+        if let (Some(prelude), Some(dir)) = (&config.prelude, path.parent()) {
+            config.prelude = Some(dir.join(prelude));
+        }
+
+        return Ok(config);
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ConsoleError> {
+        let mut config = Self::default();
+
+        for (index, line) in text.lines().enumerate() {
+            config
+                .set(line.trim())
+                .map_err(|err| ConsoleError::Line(index + 1, Box::new(err)))?;
+        }
+
+        return Ok(config);
+    }
+
+    fn set(&mut self, line: &str) -> Result<(), ConsoleError> {
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(());
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(ConsoleError::UnknownSetting(line.to_string()));
+        };
+        let (key, value) = (key.trim(), value.trim());
+        let invalid = || ConsoleError::UnknownSetting(format!("{key} = {value}"));
+
+        match key {
+            "mode" => self.float_policy = FloatPolicy::from_name(value).ok_or_else(invalid)?,
+            "angle" => self.angle_unit = AngleUnit::from_name(value).ok_or_else(invalid)?,
+            // bounded as `:format` bounds it, taking nothing but digits
+            "precision" => {
+                value.parse::<usize>().map_err(|_| invalid())?;
+                self.number_format.set(value).map_err(|_| invalid())?;
+            }
+            "format" => {
+                for setting in value.split_whitespace() {
+                    self.number_format.set(setting)?;
+                }
+            }
+            "color" | "colour" => self.color = flag(value).ok_or_else(invalid)?,
+            // quotes keep spaces at the ends
+            "prompt" => {
+                let unquoted = value
+                    .strip_prefix('"')
+                    .and_then(|value| value.strip_suffix('"'));
+                self.prompt = unquoted.unwrap_or(value).to_string();
+            }
+            "prelude" => self.prelude = Some(PathBuf::from(value)),
+            _ => return Err(ConsoleError::UnknownSetting(key.to_string())),
+        }
+
+        return Ok(());
+    }
+
+    /// The prompt before the line that will be recorded as result `n`.
+    pub fn prompt_for(&self, n: usize) -> String {
+        return self.prompt.replace("{n}", &n.to_string());
+    }
+
+    /// Sets the modes and number format of `interpreter` and runs the
+    /// prelude in it.
+    pub fn apply(&self, interpreter: &mut Interpreter) -> Result<(), ConsoleError> {
+        interpreter.set_float_policy(self.float_policy);
+        interpreter.set_angle_unit(self.angle_unit);
+        interpreter.set_number_format(self.number_format);
+
+        if let Some(path) = &self.prelude {
+            let script = fs::read_to_string(path).map_err(|err| file_access(path, err))?;
+            interpreter.run_script(&script)?;
+        }
+
+        return Ok(());
+    }
+}
@@ -0,0 +1,372 @@
+#![allow(clippy::needless_return)]
+
+use colored::*;
+use rustyline::{
+    error::ReadlineError,
+    history::{FileHistory, History},
+    CompletionType, Config as EditorConfig, Editor,
+};
+use std::{
+    cell::RefCell,
+    fs,
+    io::{self, BufRead, IsTerminal, Write},
+    mem,
+    path::PathBuf,
+    rc::Rc,
+};
+
+use super::{
+    commands::{self, is_command},
+    config::Config,
+    console_error::ConsoleError,
+    editor::ConsoleHelper,
+    interpreter::Interpreter,
+    statement::is_incomplete,
+};
+
+type ConsoleEditor = Editor<ConsoleHelper, FileHistory>;
+
+/// Entries kept in the history file, oldest dropped first.
+const HISTORY_SIZE: usize = 1000;
+
+/// Prompt for the lines of an input that leaves parentheses open.
+const CONTINUATION_PROMPT: &str = "... ";
+
+fn history_path() -> Option<PathBuf> {
+    return Config::dir().map(|dir| dir.join("history"));
+}
+
+fn io_error(err: ReadlineError) -> io::Error {
+    match err {
+        ReadlineError::Io(err) => return err,
+        err => return io::Error::other(err),
+    }
+}
+
+/// Adds `line` as the newest entry, dropping any earlier copies of it.
+fn remember(editor: &mut ConsoleEditor, line: &str) -> rustyline::Result<()> {
+    let earlier: Vec<String> = editor
+        .history()
+        .iter()
+        .filter(|entry| *entry != line)
+        .cloned()
+        .collect();
+
+    if earlier.len() != editor.history().len() {
+        let history = editor.history_mut();
+        history.clear()?;
+
+        for entry in earlier {
+            history.add_owned(entry)?;
+        }
+    }
+
+    editor.add_history_entry(line)?;
+
+    return Ok(());
+}
+
+fn save(editor: &mut ConsoleEditor, path: &PathBuf) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    return editor.save_history(path).map_err(io_error);
+}
+
+/// Whether `line` asks to end the session.
+fn is_exit(line: &str) -> bool {
+    return matches!(line.trim(), "exit" | "quit");
+}
+
+fn print_error(err: &ConsoleError) {
+    println!(
+        "{}",
+        format!(
+            "{}: {}",
+            "Error".to_string().underline(),
+            err.message().red()
+        )
+        .red()
+    )
+}
+
+/// Reads more lines while `line` leaves parentheses open, joining them with
+/// line breaks. `None` if the input was dropped with Ctrl-C.
+fn read_rest(editor: &mut ConsoleEditor, mut line: String) -> rustyline::Result<Option<String>> {
+    let mut dropped = false;
+
+    while is_incomplete(&line) {
+        if let Some(helper) = editor.helper_mut() {
+            helper.set_pending(&line);
+        }
+
+        match editor.readline(CONTINUATION_PROMPT) {
+            Ok(next) => {
+                line.push('\n');
+                line.push_str(&next);
+            }
+            Err(ReadlineError::Interrupted) => {
+                dropped = true;
+                break;
+            }
+            // left for `run` to report the parentheses that were not closed
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err),
+        }
+    }
+
+    if let Some(helper) = editor.helper_mut() {
+        helper.set_pending("");
+    }
+
+    return Ok((!dropped).then_some(line));
+}
+
+/// The interactive side of `Interpreter::console`.
+fn interactive(interpreter: &Rc<RefCell<Interpreter>>, config: &Config) -> io::Result<bool> {
+    let editor_config = EditorConfig::builder()
+        .max_history_size(HISTORY_SIZE)
+        .map_err(io_error)?
+        .history_ignore_dups(true)
+        .map_err(io_error)?
+        .auto_add_history(false)
+        .completion_type(CompletionType::List)
+        .build();
+    let mut editor: ConsoleEditor = Editor::with_config(editor_config).map_err(io_error)?;
+    editor.set_helper(Some(ConsoleHelper::new(interpreter.clone())));
+    let history = history_path();
+
+    if let Some(path) = &history {
+        // a missing file only means there is no history yet
+        let _ = editor.load_history(path);
+    }
+
+    let mut succeeded = true;
+
+    loop {
+        // the number the next result will be recorded as
+        let prompt = config.prompt_for(interpreter.borrow().results().len() + 1);
+
+        let line = match editor.readline(&prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => return Ok(succeeded),
+            Err(err) => return Err(io_error(err)),
+        };
+
+        if is_exit(&line) {
+            return Ok(succeeded);
+        }
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let line = match is_command(&line) {
+            true => line,
+            false => match read_rest(&mut editor, line).map_err(io_error)? {
+                Some(line) => line,
+                None => continue,
+            },
+        };
+
+        remember(&mut editor, &line).map_err(io_error)?;
+
+        if let Some(path) = &history {
+            if let Err(err) = save(&mut editor, path) {
+                let message = format!("Could not save history: {err}");
+                println!("{}", message.yellow());
+            }
+        }
+
+        if line.trim() == ":clear" {
+            editor.clear_screen().map_err(io_error)?;
+            continue;
+        }
+
+        if is_command(&line) {
+            match commands::run(&mut interpreter.borrow_mut(), &line) {
+                Ok(output) if output.is_empty() => {}
+                Ok(output) => println!("{output}"),
+                Err(err) => {
+                    succeeded = false;
+                    print_error(&err);
+                }
+            }
+            continue;
+        }
+
+        let mut interpreter = interpreter.borrow_mut();
+
+        // `expr as sci` writes just this result differently
+        let (source, number_format) = match interpreter.number_format().split_suffix(&line) {
+            Ok(split) => split,
+            Err(err) => {
+                succeeded = false;
+                print_error(&err);
+                continue;
+            }
+        };
+
+        match interpreter.run(source) {
+            Ok(value) => {
+                for warning in interpreter.warnings() {
+                    println!(
+                        "{}",
+                        format!(
+                            "{}: {}",
+                            "Warning".to_string().underline(),
+                            warning.message()
+                        )
+                        .yellow()
+                    )
+                }
+
+                // a definition has no value to show
+                if let Some(number) = value {
+                    interpreter.record_result(number);
+
+                    let colored = number_format.format(number).green();
+                    println!("=> {colored}")
+                }
+            }
+            Err(err) => {
+                succeeded = false;
+                print_error(&err.into());
+            }
+        }
+    }
+}
+
+impl Interpreter {
+    /// Reads and evaluates lines until `exit`, `quit` or end of input,
+    /// returning whether every line evaluated without an error.
+    ///
+    /// On a terminal this has line editing, Tab completion, Ctrl-R history
+    /// search, history kept between sessions, highlighting and `:` commands
+    /// such as `:help`. Input is run with `run`, and one that leaves
+    /// parentheses open goes on after a `...` prompt. Every result is
+    /// recorded as `ans` and `$n`, with the prompt showing the `n` of the
+    /// next one. Otherwise, as when input is piped in, it runs `run_lines`
+    /// over stdin.
+    pub fn console(&mut self) -> std::io::Result<bool> {
+        return self.console_with(&Config::default());
+    }
+
+    /// `console` with the prompt and colours of `config`. The modes and
+    /// prelude are left to `Config::apply`.
+    pub fn console_with(&mut self, config: &Config) -> std::io::Result<bool> {
+        if !config.color {
+            colored::control::set_override(false);
+        }
+
+        if !io::stdin().is_terminal() {
+            return self.run_lines(io::stdin().lock(), io::stdout(), io::stderr());
+        }
+
+        // shared with the line editor for as long as the session runs
+        let interpreter = Rc::new(RefCell::new(mem::take(self)));
+        let result = interactive(&interpreter, config);
+        *self = interpreter.take();
+
+        return result;
+    }
+
+    /// Evaluates each line of `input` without prompts or colours, writing
+    /// one result per line to `output` and warnings and errors to `errors`.
+    /// Lines starting with `:` run console commands, blank lines are skipped
+    /// and a line that leaves parentheses open goes on in the next one.
+    /// Results are recorded as `ans` and `$n`, like in the interactive
+    /// console. Stops at `exit`, `quit` or end of input and returns whether
+    /// every line evaluated without an error.
+    pub fn run_lines(
+        &mut self,
+        input: impl BufRead,
+        mut output: impl Write,
+        mut errors: impl Write,
+    ) -> io::Result<bool> {
+        let mut succeeded = true;
+        let mut pending = String::new();
+
+        for line in input.lines() {
+            let line = line?;
+
+            if pending.is_empty() {
+                if is_exit(&line) {
+                    break;
+                }
+
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                if is_command(&line) {
+                    match commands::run(self, &line) {
+                        Ok(text) if text.is_empty() => {}
+                        Ok(text) => writeln!(output, "{text}")?,
+                        Err(err) => {
+                            succeeded = false;
+                            writeln!(errors, "Error: {}", err.message())?;
+                        }
+                    }
+                    continue;
+                }
+            } else {
+                pending.push('\n');
+            }
+
+            pending.push_str(&line);
+
+            if !is_incomplete(&pending) {
+                let source = mem::take(&mut pending);
+                succeeded &= self.run_piped(&source, &mut output, &mut errors)?;
+            }
+        }
+
+        if !pending.is_empty() {
+            succeeded &= self.run_piped(&pending, &mut output, &mut errors)?;
+        }
+
+        output.flush()?;
+
+        return Ok(succeeded);
+    }
+
+    /// `run_lines` for one input, returning whether it ran without an error.
+    fn run_piped(
+        &mut self,
+        source: &str,
+        mut output: impl Write,
+        mut errors: impl Write,
+    ) -> io::Result<bool> {
+        let (source, number_format) = match self.number_format().split_suffix(source) {
+            Ok(split) => split,
+            Err(err) => {
+                writeln!(errors, "Error: {}", err.message())?;
+
+                return Ok(false);
+            }
+        };
+
+        match self.run(source) {
+            Ok(value) => {
+                for warning in self.warnings() {
+                    writeln!(errors, "Warning: {}", warning.message())?;
+                }
+
+                if let Some(number) = value {
+                    self.record_result(number);
+                    writeln!(output, "{}", number_format.format(number))?;
+                }
+
+                return Ok(true);
+            }
+            Err(err) => {
+                writeln!(errors, "Error: {}", err.message())?;
+
+                return Ok(false);
+            }
+        }
+    }
+}
@@ -0,0 +1,58 @@
+#![allow(clippy::needless_return)]
+
+use alloc::{boxed::Box, format, string::String};
+use core::fmt;
+
+use super::parser::ParseError;
+
+/// What can go wrong with console commands, scripts, saved sessions and
+/// config files, besides the expressions in them failing.
+#[derive(Debug)]
+pub enum ConsoleError {
+    /// An expression or statement that failed.
+    Parse(ParseError),
+    UnknownCommand(String),
+    /// A console command given arguments it does not take, with its usage.
+    CommandUsage(String),
+    UnknownSetting(String),
+    /// A line of a script, saved session or config file that failed,
+    /// counted from 1.
+    Line(usize, Box<ConsoleError>),
+    /// A file that could not be read or written, with the reason.
+    FileAccess(String, String),
+}
+
+impl ConsoleError {
+    pub fn message(&self) -> String {
+        match self {
+            Self::Parse(err) => return err.message(),
+            Self::UnknownCommand(command) => return format!("Unknown command: `{command}`"),
+            Self::CommandUsage(usage) => return format!("Usage: `{usage}`"),
+            Self::UnknownSetting(setting) => return format!("Unknown setting: `{setting}`"),
+            Self::Line(line, error) => return format!("Line {line}: {}", error.message()),
+            Self::FileAccess(path, reason) => return format!("Can not access `{path}`: {reason}"),
+        }
+    }
+}
+
+impl From<ParseError> for ConsoleError {
+    fn from(err: ParseError) -> Self {
+        return Self::Parse(err);
+    }
+}
+
+impl fmt::Display for ConsoleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+impl core::error::Error for ConsoleError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Self::Parse(err) => return Some(err),
+            Self::Line(_, err) => return Some(&**err),
+            _ => return None,
+        }
+    }
+}
@@ -0,0 +1,57 @@
+#![allow(clippy::needless_return)]
+
+use alloc::string::ToString;
+use rust_decimal::{Decimal, MathematicalOps};
+
+use super::{lexer::TokenKind, number::Number, parser::ParseError};
+
+/// Decimal fractions with 28 digits, so `0.1 + 0.2` is exactly `0.3`.
+/// Powers with a fractional exponent, `pi` and the functions are rounded to
+/// those digits.
+impl Number for Decimal {
+    fn parse_literal(literal: &str) -> Option<Self> {
+        return Decimal::from_str_exact(literal).ok();
+    }
+
+    fn from_f32(value: f32) -> Option<Self> {
+        // the shortest digits that read back as the same `f32`
+        return Decimal::from_str_exact(&value.to_string()).ok();
+    }
+
+    fn pi() -> Option<Self> {
+        return Some(Decimal::PI);
+    }
+
+    fn binary(&self, operator: &TokenKind, rhs: &Self) -> Result<Self, ParseError> {
+        let value = match operator {
+            TokenKind::Plus => self.checked_add(*rhs),
+            TokenKind::Minus => self.checked_sub(*rhs),
+            TokenKind::Asterix => self.checked_mul(*rhs),
+            TokenKind::Slash if rhs.is_zero() => return Err(ParseError::DivisionByZero),
+            TokenKind::Slash => self.checked_div(*rhs),
+            // a negative base only has real powers for whole exponents
+            TokenKind::Power if self.is_sign_negative() && !rhs.is_integer() => {
+                return Err(ParseError::OutOfDomain(self.written(operator, rhs)));
+            }
+            TokenKind::Power => self.checked_powd(*rhs),
+            unknown => return Err(ParseError::UnexpectedOperator(unknown.clone())),
+        };
+
+        return value.ok_or_else(|| ParseError::NonFinite(self.written(operator, rhs)));
+    }
+
+    fn apply(&self, function: &TokenKind) -> Result<Self, ParseError> {
+        let value = match function {
+            TokenKind::Sin => self.checked_sin(),
+            TokenKind::Cos => self.checked_cos(),
+            TokenKind::Tan => self.checked_tan(),
+            TokenKind::Log if self.is_sign_negative() || self.is_zero() => {
+                return Err(ParseError::OutOfDomain(self.applied(function)));
+            }
+            TokenKind::Log => self.checked_log10(),
+            unknown => return Err(ParseError::UnexpectedOperator(unknown.clone())),
+        };
+
+        return value.ok_or_else(|| ParseError::NonFinite(self.applied(function)));
+    }
+}
@@ -0,0 +1,241 @@
+#![allow(clippy::needless_return)]
+
+use colored::Colorize;
+use rustyline::{
+    completion::{Completer, Pair},
+    highlight::Highlighter,
+    hint::Hinter,
+    validate::Validator,
+    Context, Helper,
+};
+use std::{
+    borrow::Cow::{self, Borrowed, Owned},
+    cell::RefCell,
+    rc::Rc,
+};
+
+use super::{
+    commands::{is_command, COMMANDS},
+    interpreter::Interpreter,
+    lexer::{Lexer, Token, TokenKind},
+    registry,
+};
+
+/// Line editor support for `Interpreter::console`, sharing the session's
+/// interpreter so it sees the variables defined so far.
+pub struct ConsoleHelper {
+    interpreter: Rc<RefCell<Interpreter>>,
+    /// Lines already read of an input that goes on in the current one.
+    pending: String,
+}
+
+impl ConsoleHelper {
+    pub fn new(interpreter: Rc<RefCell<Interpreter>>) -> Self {
+        return Self {
+            interpreter,
+            pending: String::new(),
+        };
+    }
+
+    pub fn set_pending(&mut self, pending: &str) {
+        self.pending = pending.to_string();
+    }
+
+    /// Candidates for the name being typed, shown with their signature and
+    /// description when there is more than one.
+    fn names(&self, prefix: &str) -> Vec<Pair> {
+        let interpreter = self.interpreter.borrow();
+
+        return interpreter
+            .registry()
+            .complete(prefix)
+            .into_iter()
+            .map(|name| {
+                let display = match registry::describe(name) {
+                    Some((signature, doc)) => format!("{signature:<12} {doc}"),
+                    None => match (interpreter.variable(name), interpreter.function(name)) {
+                        (Some(value), _) => format!("{name:<12} = {value:?}"),
+                        (None, Some(function)) => function.to_string(),
+                        (None, None) => name.to_string(),
+                    },
+                };
+
+                Pair {
+                    display,
+                    replacement: name.to_string(),
+                }
+            })
+            .collect();
+    }
+}
+
+/// Start of the name or command that ends at `pos`.
+fn word_start(line: &str, pos: usize) -> usize {
+    return line[..pos]
+        .char_indices()
+        .rev()
+        .take_while(|(_, chr)| chr.is_alphabetic() || *chr == ':')
+        .last()
+        .map_or(pos, |(index, _)| index);
+}
+
+impl Completer for ConsoleHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = word_start(line, pos);
+        let word = &line[start..pos];
+
+        if start == 0 && word.starts_with(':') {
+            let commands = COMMANDS
+                .iter()
+                .filter(|(command, ..)| command.starts_with(word))
+                .map(|(command, usage, doc)| Pair {
+                    display: format!("{usage:<12} {doc}"),
+                    replacement: format!("{command} "),
+                })
+                .collect();
+
+            return Ok((start, commands));
+        }
+
+        // names are alphabetic, a `:` can only start a command
+        let start = start + word.rfind(':').map_or(0, |index| index + 1);
+
+        return Ok((start, self.names(&line[start..pos])));
+    }
+}
+
+/// Indices of the parenthesis at `cursor`, or just before it, and of its
+/// partner.
+fn matching_parens(tokens: &[Token], cursor: usize) -> Option<(usize, usize)> {
+    let at = |offset: usize| {
+        tokens.iter().position(|token| {
+            token.span().start == offset
+                && matches!(token.kind(), TokenKind::OpenParen | TokenKind::CloseParen)
+        })
+    };
+    let index = at(cursor).or_else(|| at(cursor.checked_sub(1)?))?;
+    let mut depth = 0usize;
+
+    if tokens[index].kind().is_open_paren() {
+        for (partner, token) in tokens.iter().enumerate().skip(index + 1) {
+            match token.kind() {
+                TokenKind::OpenParen => depth += 1,
+                TokenKind::CloseParen if depth == 0 => return Some((index, partner)),
+                TokenKind::CloseParen => depth -= 1,
+                _ => {}
+            }
+        }
+    } else {
+        for (partner, token) in tokens.iter().enumerate().take(index).rev() {
+            match token.kind() {
+                TokenKind::CloseParen => depth += 1,
+                TokenKind::OpenParen if depth == 0 => return Some((index, partner)),
+                TokenKind::OpenParen => depth -= 1,
+                _ => {}
+            }
+        }
+    }
+
+    return None;
+}
+
+fn paint(kind: &TokenKind, text: &str) -> String {
+    let painted = match kind {
+        TokenKind::Number(_) => text.yellow(),
+        TokenKind::Sin | TokenKind::Cos | TokenKind::Tan | TokenKind::Log => text.cyan(),
+        TokenKind::Pi => text.magenta(),
+        TokenKind::Identifier(_) => text.blue(),
+        TokenKind::OpenParen | TokenKind::CloseParen => text.normal(),
+        _ => text.bold(),
+    };
+
+    return painted.to_string();
+}
+
+impl Hinter for ConsoleHelper {
+    type Hint = String;
+
+    /// The result of the input so far, shown below the line while the
+    /// cursor is at the end.
+    fn hint(&self, line: &str, pos: usize, _: &Context<'_>) -> Option<String> {
+        if pos < line.len() || line.trim().is_empty() || is_command(line) {
+            return None;
+        }
+
+        let input = match self.pending.is_empty() {
+            true => line.to_string(),
+            false => format!("{}\n{line}", self.pending),
+        };
+        let interpreter = self.interpreter.borrow();
+        let (source, number_format) = interpreter.number_format().split_suffix(&input).ok()?;
+        let value = interpreter.preview(source).ok()??;
+
+        return Some(format!("\n= {}", number_format.format(value)));
+    }
+}
+
+impl Highlighter for ConsoleHelper {
+    /// Colours each token by its kind, anything the lexer skipped over in
+    /// red, and the parentheses at the cursor when they match.
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        if line.is_empty() || is_command(line) {
+            return Borrowed(line);
+        }
+
+        let (tokens, _) = Lexer::new().lex_all(line);
+        let chars: Vec<char> = line.chars().collect();
+        let cursor = line[..pos].chars().count();
+        let matched = matching_parens(&tokens, cursor);
+        let mut highlighted = String::with_capacity(line.len());
+        let mut offset = 0;
+
+        let skipped = |highlighted: &mut String, from: usize, to: usize| {
+            for chr in &chars[from..to] {
+                if chr.is_whitespace() {
+                    highlighted.push(*chr);
+                } else {
+                    highlighted.push_str(&chr.to_string().red().to_string());
+                }
+            }
+        };
+
+        for (index, token) in tokens.iter().enumerate() {
+            let span = token.span();
+            skipped(&mut highlighted, offset, span.start);
+
+            let text: String = chars[span.start..span.end].iter().collect();
+            match matched {
+                Some((at, partner)) if index == at || index == partner => {
+                    highlighted.push_str(&text.bold().green().to_string())
+                }
+                _ => highlighted.push_str(&paint(token.kind(), &text)),
+            }
+
+            offset = span.end;
+        }
+
+        skipped(&mut highlighted, offset, chars.len());
+
+        return Owned(highlighted);
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        return Owned(hint.dimmed().to_string());
+    }
+
+    /// Highlights again whenever the cursor moves, to follow parentheses.
+    fn highlight_char(&self, line: &str, _: usize, forced: bool) -> bool {
+        return !forced && !line.is_empty();
+    }
+}
+
+impl Validator for ConsoleHelper {}
+
+impl Helper for ConsoleHelper {}
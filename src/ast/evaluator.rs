@@ -0,0 +1,195 @@
+#![allow(clippy::needless_return)]
+
+use alloc::{format, vec::Vec};
+use core::f32::consts::PI;
+
+use super::{
+    lexer::{Token, TokenKind},
+    limits::Budget,
+    math,
+    parser::{FloatPolicy, ParseError},
+    trace::{Level, Stage, Tracer},
+};
+
+/// Values that fit here never touch the heap while evaluating.
+pub const INLINE_STACK: usize = 32;
+
+/// Operand stack that only allocates once an expression nests deeper than
+/// `INLINE_STACK` values.
+struct NumberStack {
+    inline: [f32; INLINE_STACK],
+    spilled: Vec<f32>,
+    len: usize,
+}
+
+impl NumberStack {
+    fn new() -> Self {
+        return Self {
+            inline: [0.0; INLINE_STACK],
+            spilled: Vec::new(),
+            len: 0,
+        };
+    }
+
+    fn push(&mut self, value: f32) {
+        if self.len < INLINE_STACK {
+            self.inline[self.len] = value;
+        } else {
+            self.spilled.push(value);
+        }
+
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> Option<f32> {
+        if self.len == 0 {
+            return None;
+        }
+
+        self.len -= 1;
+
+        if self.len < INLINE_STACK {
+            return Some(self.inline[self.len]);
+        }
+
+        return self.spilled.pop();
+    }
+}
+
+/// Float policy checks shared by the token walker in `run`, which backs
+/// `Parser::execute`, and the bytecode in `Program::run`.
+pub struct Evaluation<'e> {
+    pub float_policy: FloatPolicy,
+    pub warnings: &'e mut Vec<ParseError>,
+    pub tracer: &'e Tracer,
+    pub budget: Budget<'e>,
+}
+
+impl<'e> Evaluation<'e> {
+    pub fn in_domain(kind: &TokenKind, arg: f32) -> bool {
+        match kind {
+            TokenKind::Log => arg > 0.0,
+            // tan has poles wherever cos crosses zero, which f32 can only approximate
+            TokenKind::Tan => arg.is_finite() && math::cos(arg).abs() > f32::EPSILON,
+            TokenKind::Sin | TokenKind::Cos => arg.is_finite(),
+            _ => true,
+        }
+    }
+
+    pub fn check(&mut self, issue: ParseError, value: f32) -> Result<f32, ParseError> {
+        match self.float_policy {
+            FloatPolicy::Strict => return Err(issue),
+            FloatPolicy::Ieee => {}
+            FloatPolicy::Warn => self.warnings.push(issue),
+        }
+
+        return Ok(value);
+    }
+
+    pub fn apply_function(&mut self, kind: &TokenKind, arg: f32) -> Result<f32, ParseError> {
+        let value = match kind {
+            TokenKind::Sin => math::sin(arg),
+            TokenKind::Cos => math::cos(arg),
+            TokenKind::Tan => math::tan(arg),
+            TokenKind::Log => math::log10(arg),
+            unknown => return Err(ParseError::UnexpectedOperator(unknown.clone())),
+        };
+
+        if !Self::in_domain(kind, arg) {
+            let operation = format!("{kind}{arg:?}");
+            return self.check(ParseError::OutOfDomain(operation), value);
+        }
+        if !value.is_finite() {
+            let operation = format!("{kind}{arg:?}");
+            return self.check(ParseError::NonFinite(operation), value);
+        }
+
+        return Ok(value);
+    }
+
+    /// Applies the float policy to `value`, the result of `rhs <kind> lhs`
+    /// where `lhs` was on top of the stack.
+    pub fn check_binary(
+        &mut self,
+        kind: &TokenKind,
+        lhs: f32,
+        rhs: f32,
+        value: f32,
+    ) -> Result<f32, ParseError> {
+        if matches!(kind, TokenKind::Slash) && lhs == 0.0 {
+            return self.check(ParseError::DivisionByZero, value);
+        }
+        if !value.is_finite() && lhs.is_finite() && rhs.is_finite() {
+            let operation = format!("{rhs:?}{kind}{lhs:?}");
+            return self.check(ParseError::NonFinite(operation), value);
+        }
+
+        return Ok(value);
+    }
+
+    //reverse polish notation
+    pub fn run<'t, I, F>(&mut self, tokens: I, mut resolve: F) -> Result<f32, ParseError>
+    where
+        I: Iterator<Item = &'t Token>,
+        F: FnMut(&str) -> Result<f32, ParseError>,
+    {
+        let mut number_stack = NumberStack::new();
+
+        for (step, token) in tokens.enumerate() {
+            self.budget.check(step)?;
+
+            if let TokenKind::Pi = token.kind() {
+                number_stack.push(PI);
+                continue;
+            }
+            if let TokenKind::Number(value) = token.kind() {
+                number_stack.push(*value);
+                continue;
+            }
+
+            let operation: fn(f32, f32) -> f32 = match token.kind() {
+                TokenKind::Identifier(ident) => {
+                    number_stack.push(resolve(ident)?);
+                    continue;
+                }
+                TokenKind::Plus => |x, y| x + y,
+                TokenKind::Asterix => |x, y| x * y,
+                TokenKind::Minus => |x, y| y - x,
+                TokenKind::Power => |x, y| math::powf(y, x),
+                TokenKind::Slash => |x, y| y / x,
+                TokenKind::Sin | TokenKind::Cos | TokenKind::Tan | TokenKind::Log => {
+                    let rhs = number_stack.pop().ok_or(ParseError::MissingExpression)?;
+
+                    let value = self.apply_function(token.kind(), rhs)?;
+
+                    self.tracer.emit(Level::Trace, Stage::Evaluator, || {
+                        format!("{}{rhs:?} = {value:?}", token.kind())
+                    });
+
+                    number_stack.push(value);
+                    continue;
+                }
+                unknown => return Err(ParseError::UnexpectedOperator(unknown.clone())),
+            };
+
+            let (Some(lhs), Some(rhs)) = (number_stack.pop(), number_stack.pop()) else {
+                return Err(ParseError::MissingExpression);
+            };
+            let value = self.check_binary(token.kind(), lhs, rhs, operation(lhs, rhs))?;
+
+            self.tracer.emit(Level::Trace, Stage::Evaluator, || {
+                format!("{rhs:?}{}{lhs:?} = {value:?}", token.kind())
+            });
+
+            number_stack.push(value)
+        }
+
+        let result = number_stack.pop().unwrap_or(0.0);
+
+        self.tracer.emit(Level::Debug, Stage::Evaluator, || {
+            format!("result: {result:?}")
+        });
+
+        return Ok(result);
+    }
+}
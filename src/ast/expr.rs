@@ -0,0 +1,94 @@
+#![allow(clippy::needless_return)]
+
+use alloc::{boxed::Box, string::String, vec, vec::Vec};
+use core::mem;
+
+use super::lexer::{Span, TokenKind};
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ExprKind {
+    Number(f32),
+    Pi,
+    Identifier(String),
+    /// A built-in function such as `sin` applied to its argument.
+    Call(TokenKind, Box<Expr>),
+    Binary(TokenKind, Box<Expr>, Box<Expr>),
+    /// Placeholder for a part of the input that could not be parsed.
+    Error,
+}
+
+/// Node of the tree built by `Parser::parse_recovering`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Expr {
+    kind: ExprKind,
+    span: Span,
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        return Self { kind, span };
+    }
+
+    pub fn error(span: Span) -> Self {
+        return Self::new(ExprKind::Error, span);
+    }
+
+    pub fn binary(operator: TokenKind, lhs: Expr, rhs: Expr) -> Self {
+        let span = lhs.span.to(rhs.span);
+
+        return Self::new(
+            ExprKind::Binary(operator, Box::new(lhs), Box::new(rhs)),
+            span,
+        );
+    }
+
+    pub fn kind(&self) -> &ExprKind {
+        return &self.kind;
+    }
+
+    pub fn into_kind(mut self) -> ExprKind {
+        return mem::replace(&mut self.kind, ExprKind::Error);
+    }
+
+    pub fn span(&self) -> Span {
+        return self.span;
+    }
+
+    /// Whether any part of the tree is an `ExprKind::Error` placeholder.
+    pub fn has_errors(&self) -> bool {
+        let mut pending = vec![self];
+
+        while let Some(expr) = pending.pop() {
+            match &expr.kind {
+                ExprKind::Error => return true,
+                ExprKind::Call(_, arg) => pending.push(arg),
+                ExprKind::Binary(_, lhs, rhs) => pending.extend([&**lhs, &**rhs]),
+                _ => {}
+            }
+        }
+
+        return false;
+    }
+}
+
+/// A long chain like `1 + 1 + ... + 1` is as deep as it is long, too deep to
+/// drop recursively, so children are detached and dropped one at a time.
+impl Drop for Expr {
+    fn drop(&mut self) {
+        let mut pending: Vec<Box<Expr>> = Vec::new();
+        let mut kind = mem::replace(&mut self.kind, ExprKind::Error);
+
+        loop {
+            match kind {
+                ExprKind::Call(_, arg) => pending.push(arg),
+                ExprKind::Binary(_, lhs, rhs) => pending.extend([lhs, rhs]),
+                _ => {}
+            }
+
+            let Some(mut child) = pending.pop() else {
+                return;
+            };
+            kind = mem::replace(&mut child.kind, ExprKind::Error);
+        }
+    }
+}
@@ -0,0 +1,373 @@
+#![allow(clippy::needless_return)]
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt;
+
+use super::console_error::ConsoleError;
+
+/// Exponents outside this range are written in scientific notation by
+/// `Notation::Auto`, as `{:?}` does.
+const POSITIONAL_EXPONENTS: core::ops::Range<i32> = -5..16;
+
+/// Significant digits an `f32` holds reliably, which smart rounding keeps.
+const SMART_DIGITS: usize = 7;
+
+/// Significant digits that tell any two `f32` apart.
+const MAX_PRECISION: usize = 9;
+
+/// Digits after the point that reach the smallest `f32`, about `1e-45`.
+const MAX_DECIMALS: usize = 45;
+
+const SI_PREFIXES: &[(i32, &str)] = &[
+    (-30, "q"),
+    (-27, "r"),
+    (-24, "y"),
+    (-21, "z"),
+    (-18, "a"),
+    (-15, "f"),
+    (-12, "p"),
+    (-9, "n"),
+    (-6, "µ"),
+    (-3, "m"),
+    (0, ""),
+    (3, "k"),
+    (6, "M"),
+    (9, "G"),
+    (12, "T"),
+    (15, "P"),
+    (18, "E"),
+    (21, "Z"),
+    (24, "Y"),
+    (27, "R"),
+    (30, "Q"),
+];
+
+/// Where the point goes and how the exponent is shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Notation {
+    /// Positional, switching to scientific for very large or small values.
+    #[default]
+    Auto,
+    /// Always positional.
+    Fixed,
+    /// One digit before the point, `1.5e-7`.
+    Scientific,
+    /// Exponents that are multiples of 3, `12.5e3`.
+    Engineering,
+    /// Engineering with SI prefixes instead of exponents, `12.5k`, `4.7µ`.
+    Si,
+}
+
+impl Notation {
+    pub fn from_name(name: &str) -> Option<Notation> {
+        let notation = match name.to_lowercase().as_str() {
+            "auto" => Notation::Auto,
+            "fixed" => Notation::Fixed,
+            "sci" | "scientific" => Notation::Scientific,
+            "eng" | "engineering" => Notation::Engineering,
+            "si" => Notation::Si,
+            _ => return None,
+        };
+
+        return Some(notation);
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Notation::Auto => return "auto",
+            Notation::Fixed => return "fixed",
+            Notation::Scientific => return "sci",
+            Notation::Engineering => return "eng",
+            Notation::Si => return "si",
+        }
+    }
+}
+
+/// How results are written out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct NumberFormat {
+    pub notation: Notation,
+    /// Significant digits to round to, at most 9. `None` writes the
+    /// shortest digits that read back as the same `f32`.
+    pub precision: Option<usize>,
+    /// Digits after the point, written even when they are zeros, at most 45.
+    /// Used instead of `precision` when set.
+    pub decimals: Option<usize>,
+    /// Put between groups of three digits before the point. Whitespace is
+    /// not allowed, since the settings are read back split at whitespace.
+    pub separator: Option<char>,
+    /// Rounds away the noise of `f32` arithmetic, so `0.1 + 0.2` shows as
+    /// `0.3` rather than `0.30000001`.
+    pub smart: bool,
+}
+
+/// The significant digits of a magnitude, the first one standing for
+/// `10^exponent`.
+struct Digits {
+    digits: String,
+    exponent: i32,
+}
+
+impl Digits {
+    /// `magnitude` rounded to `significant` digits, or to the shortest
+    /// digits that read back as it, without trailing zeros.
+    fn new(magnitude: f32, significant: Option<usize>) -> Self {
+        let scientific = match significant {
+            Some(significant) => format!("{:.*e}", significant.max(1) - 1, magnitude),
+            None => format!("{magnitude:e}"),
+        };
+        let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+        let digits: String = mantissa.chars().filter(char::is_ascii_digit).collect();
+
+        let digits = match digits.trim_end_matches('0') {
+            "" => String::from("0"),
+            digits => digits.to_string(),
+        };
+
+        return Self {
+            digits,
+            exponent: exponent.parse().unwrap_or(0),
+        };
+    }
+
+    /// The digits before and after the point when the first one stands for
+    /// `10^shift`.
+    fn split(&self, shift: i32) -> (String, String) {
+        match usize::try_from(shift) {
+            Ok(shift) if shift < self.digits.len() => {
+                let (whole, fraction) = self.digits.split_at(shift + 1);
+                return (whole.to_string(), fraction.to_string());
+            }
+            Ok(shift) => {
+                let whole = format!("{:0<width$}", self.digits, width = shift + 1);
+                return (whole, String::new());
+            }
+            Err(_) => {
+                let width = shift.unsigned_abs() as usize - 1 + self.digits.len();
+                return (String::from("0"), format!("{:0>width$}", self.digits));
+            }
+        }
+    }
+}
+
+impl NumberFormat {
+    pub fn format(&self, value: f32) -> String {
+        // the fields are public, so they are bounded here as well as in `set`
+        let bounded = Self {
+            precision: self.precision.map(|precision| precision.min(MAX_PRECISION)),
+            decimals: self.decimals.map(|decimals| decimals.min(MAX_DECIMALS)),
+            ..*self
+        };
+
+        return bounded.write(value);
+    }
+
+    fn write(&self, value: f32) -> String {
+        if !value.is_finite() {
+            return format!("{value:?}");
+        }
+
+        let sign = if value.is_sign_negative() { "-" } else { "" };
+        let magnitude = value.abs();
+
+        let written = match (self.notation, self.decimals) {
+            (Notation::Auto | Notation::Fixed, Some(decimals)) => {
+                let fixed = format!("{magnitude:.decimals$}");
+                let (whole, fraction) = fixed.split_once('.').unwrap_or((&fixed, ""));
+
+                self.join(whole, fraction, "")
+            }
+            (Notation::Fixed, None) => self.positional(&self.digits(magnitude, 0)),
+            (Notation::Auto, None) => {
+                let digits = self.digits(magnitude, 0);
+
+                match POSITIONAL_EXPONENTS.contains(&digits.exponent) {
+                    true => self.positional(&digits),
+                    false => format!("{}e{}", self.mantissa(&digits, 0), digits.exponent),
+                }
+            }
+            (Notation::Scientific, _) => {
+                let digits = self.digits(magnitude, 0);
+
+                format!("{}e{}", self.mantissa(&digits, 0), digits.exponent)
+            }
+            (Notation::Engineering | Notation::Si, _) => {
+                // rounding can carry into the next power of ten, and so
+                // into the next multiple of 3
+                let shift = Digits::new(magnitude, None).exponent.rem_euclid(3);
+                let digits = self.digits(magnitude, shift);
+                let shift = digits.exponent.rem_euclid(3);
+                let exponent = digits.exponent - shift;
+                let mantissa = self.mantissa(&digits, shift);
+
+                let prefix = SI_PREFIXES
+                    .iter()
+                    .find(|(power, _)| *power == exponent)
+                    .map(|(_, prefix)| *prefix);
+
+                // past the prefixes there are, the exponent is written
+                match (self.notation, prefix) {
+                    (Notation::Si, Some(prefix)) => format!("{mantissa}{prefix}"),
+                    _ => format!("{mantissa}e{exponent}"),
+                }
+            }
+        };
+
+        return format!("{sign}{written}");
+    }
+
+    /// The digits to write of `magnitude` when `shift` of them come before
+    /// the point after the first.
+    fn digits(&self, magnitude: f32, shift: i32) -> Digits {
+        if let Some(decimals) = self.decimals {
+            return Digits::new(magnitude, Some(decimals + shift as usize + 1));
+        }
+
+        let significant = match (self.precision, self.smart) {
+            (Some(precision), true) => Some(precision.min(SMART_DIGITS)),
+            (None, true) => Some(SMART_DIGITS),
+            (precision, false) => precision,
+        };
+
+        return Digits::new(magnitude, significant);
+    }
+
+    /// `digits` without an exponent, keeping at least one digit after the
+    /// point like `{:?}` does.
+    fn positional(&self, digits: &Digits) -> String {
+        let (whole, fraction) = digits.split(digits.exponent);
+
+        return self.join(&whole, &fraction, ".0");
+    }
+
+    /// `digits` with the point after the first `shift + 1` of them, to be
+    /// followed by an exponent or prefix.
+    fn mantissa(&self, digits: &Digits, shift: i32) -> String {
+        let (whole, fraction) = digits.split(shift);
+
+        return self.join(&whole, &fraction, "");
+    }
+
+    /// `whole.fraction`, grouped, with `decimals` digits after the point if
+    /// set. Otherwise trailing zeros are dropped and `empty` is written for
+    /// no fraction at all.
+    fn join(&self, whole: &str, fraction: &str, empty: &str) -> String {
+        let whole = match self.separator {
+            Some(separator) => group(whole, separator),
+            None => whole.to_string(),
+        };
+
+        let fraction = match self.decimals {
+            Some(decimals) => format!("{fraction:0<decimals$.decimals$}"),
+            None => fraction.trim_end_matches('0').to_string(),
+        };
+
+        match fraction.is_empty() {
+            true => return format!("{whole}{empty}"),
+            false => return format!("{whole}.{fraction}"),
+        }
+    }
+
+    /// Changes one setting, as written after `:format` or `as`: a notation,
+    /// `N` significant digits up to 9, `.N` digits after the point up to 45,
+    /// `group`, `group=C` or `nogroup`, `smart`, or `exact` for every digit.
+    pub fn set(&mut self, setting: &str) -> Result<(), ConsoleError> {
+        let unknown = || ConsoleError::UnknownSetting(setting.to_string());
+
+        if let Some(notation) = Notation::from_name(setting) {
+            self.notation = notation;
+        } else if let Some(decimals) = setting.strip_prefix('.') {
+            let decimals = decimals.parse().map_err(|_| unknown())?;
+
+            if decimals > MAX_DECIMALS {
+                return Err(unknown());
+            }
+            self.decimals = Some(decimals);
+        } else if let Ok(precision) = setting.parse::<usize>() {
+            if precision > MAX_PRECISION {
+                return Err(unknown());
+            }
+            self.precision = (precision > 0).then_some(precision);
+            self.decimals = None;
+        } else if let Some(separator) = setting.strip_prefix("group=") {
+            let mut chars = separator.chars();
+            let separator = chars.next().ok_or_else(unknown)?;
+
+            if separator.is_whitespace() || chars.next().is_some() {
+                return Err(unknown());
+            }
+            self.separator = Some(separator);
+        } else {
+            match setting.to_lowercase().as_str() {
+                "group" => self.separator = Some(','),
+                "nogroup" => self.separator = None,
+                "smart" => self.smart = true,
+                "exact" => {
+                    self.smart = false;
+                    self.precision = None;
+                    self.decimals = None;
+                }
+                _ => return Err(unknown()),
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// Splits `expression as sci 3` into the expression and this format
+    /// with the settings after `as` applied. Input without `as` is
+    /// returned whole, along with this format.
+    pub fn split_suffix<'s>(&self, source: &'s str) -> Result<(&'s str, Self), ConsoleError> {
+        let Some((expression, settings)) = source.rsplit_once(" as ") else {
+            return Ok((source, *self));
+        };
+
+        let mut format = *self;
+        for setting in settings.split_whitespace() {
+            format.set(setting)?;
+        }
+
+        return Ok((expression, format));
+    }
+}
+
+/// The settings of the format, as `set` reads them, `sci 4 group smart`.
+impl fmt::Display for NumberFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut settings = Vec::from([self.notation.name().to_string()]);
+
+        match (self.decimals, self.precision) {
+            (Some(decimals), _) => settings.push(format!(".{decimals}")),
+            (None, Some(precision)) => settings.push(precision.to_string()),
+            (None, None) => {}
+        }
+        match self.separator {
+            Some(',') => settings.push(String::from("group")),
+            Some(separator) => settings.push(format!("group={separator}")),
+            None => {}
+        }
+        if self.smart {
+            settings.push(String::from("smart"));
+        }
+
+        write!(f, "{}", settings.join(" "))
+    }
+}
+
+/// `digits` with `separator` between groups of three, counted from the right.
+fn group(digits: &str, separator: char) -> String {
+    let mut grouped = String::with_capacity(digits.len() * 2);
+
+    for (index, digit) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index).is_multiple_of(3) {
+            grouped.push(separator);
+        }
+        grouped.push(digit);
+    }
+
+    return grouped;
+}
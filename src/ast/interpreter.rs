@@ -1,13 +1,77 @@
+#![allow(clippy::needless_return)]
+
 use super::{
-    lexer::Lexer,
-    parser::{ParseError, Parser},
+    cache::{CacheStats, ExpressionCache},
+    compiled::CompiledExpr,
+    expr::Expr,
+    format::NumberFormat,
+    lexer::{Lexer, Span, Token},
+    limits::{Budget, Deadline, Limits},
+    parser::{AngleUnit, Diagnostic, FloatPolicy, ParseError, Parser},
+    registry::Registry,
+    statement::{self, Function, Statement},
+    trace::{Event, Level, Tracer},
 };
-use colored::*;
-use std::io::{self, Write};
+use alloc::{collections::VecDeque, string::ToString, sync::Arc, vec, vec::Vec};
 
 pub struct Interpreter {
     lexer: Lexer,
     parser: Parser,
+    optimize: bool,
+    cache: ExpressionCache,
+    limits: Limits,
+    number_format: NumberFormat,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+/// Lexes and parses `expression` within `limits`.
+fn parse_with(
+    lexer: &mut Lexer,
+    parser: &mut Parser,
+    limits: &Limits,
+    expression: &str,
+    deadline: &Deadline,
+) -> Result<VecDeque<Token>, ParseError> {
+    if expression.is_empty() {
+        return Err(ParseError::ExpressionEmpty);
+    }
+    limits
+        .check_input(expression)
+        .map_err(ParseError::LimitExceeded)?;
+
+    let token_stream = lexer.lex(expression)?;
+    limits
+        .check_tokens(&token_stream)
+        .map_err(|(limit, _)| ParseError::LimitExceeded(limit))?;
+    deadline.check()?;
+
+    return parse_tokens(parser, limits, &token_stream, deadline);
+}
+
+/// Expands calls of user functions in `tokens` and parses the result.
+fn parse_tokens(
+    parser: &mut Parser,
+    limits: &Limits,
+    tokens: &[Token],
+    deadline: &Deadline,
+) -> Result<VecDeque<Token>, ParseError> {
+    let expanded = statement::expand(tokens, parser.registry(), limits, deadline)
+        .map_err(Diagnostic::into_error)?;
+    // a call can expand to more deeply nested parentheses than were written
+    limits
+        .check_tokens(&expanded)
+        .map_err(|(limit, _)| ParseError::LimitExceeded(limit))?;
+    deadline.check()?;
+
+    let token_queue = parser.parse(expanded)?;
+    deadline.check()?;
+
+    return Ok(token_queue);
 }
 
 impl<'a> Interpreter {
@@ -15,51 +79,407 @@ impl<'a> Interpreter {
         return Self {
             lexer: Lexer::new(),
             parser: Parser::new(),
+            optimize: true,
+            cache: ExpressionCache::default(),
+            limits: Limits::default(),
+            number_format: NumberFormat::default(),
         };
     }
 
-    pub fn console(&mut self) -> std::io::Result<()> {
-        let mut buffer = String::new();
+    pub fn float_policy(&self) -> FloatPolicy {
+        return self.parser.float_policy();
+    }
 
-        loop {
-            print!(">>> ");
+    pub fn set_float_policy(&mut self, policy: FloatPolicy) {
+        if policy != self.float_policy() {
+            self.cache.clear();
+        }
 
-            io::stdout().flush()?;
+        self.parser.set_float_policy(policy);
+    }
 
-            std::io::stdin().read_line(&mut buffer)?;
-            buffer = buffer.replace(['\r', '\n'], "");
+    pub fn angle_unit(&self) -> AngleUnit {
+        return self.parser.angle_unit();
+    }
 
-            match self.interpret(buffer.as_str()) {
-                Ok(number) => {
-                    let colored = format!("{number:?}").green();
-                    println!("=> {colored}")
-                }
-                Err(err) => println!(
-                    "{}",
-                    format!(
-                        "{}: {}",
-                        "Error".to_string().underline(),
-                        format!("{}", err.message().red())
-                    )
-                    .red()
-                ),
-            }
+    pub fn set_angle_unit(&mut self, unit: AngleUnit) {
+        if unit != self.angle_unit() {
+            self.cache.clear();
+        }
+
+        self.parser.set_angle_unit(unit);
+    }
+
+    pub fn number_format(&self) -> NumberFormat {
+        return self.number_format;
+    }
+
+    /// How the console writes results out. Evaluation is not affected.
+    pub fn set_number_format(&mut self, format: NumberFormat) {
+        self.number_format = format;
+    }
+
+    pub fn optimize(&self) -> bool {
+        return self.optimize;
+    }
 
-            buffer.clear();
+    /// Whether `compile` and `interpret` run `Program::optimize`. On by
+    /// default; turn it off when exact IEEE semantics are required.
+    pub fn set_optimize(&mut self, enabled: bool) {
+        if enabled != self.optimize {
+            self.cache.clear();
         }
 
-        return Ok(());
+        self.optimize = enabled;
+    }
+
+    pub fn limits(&self) -> &Limits {
+        return &self.limits;
+    }
+
+    /// Bounds the work done by `compile`, `interpret`, `evaluate` and
+    /// `diagnose`. Going over a limit is a `ParseError::LimitExceeded`.
+    pub fn set_limits(&mut self, limits: Limits) {
+        // cached expressions were only checked against the old limits
+        self.cache.clear();
+        self.limits = limits;
     }
 
+    /// Keeps up to `capacity` compiled expressions so `interpret` does not
+    /// lex and parse the same source again. Off (0) by default.
+    pub fn set_cache_capacity(&mut self, capacity: usize) {
+        self.cache.set_capacity(capacity);
+    }
+
+    pub fn cache_stats(&self) -> CacheStats {
+        return self.cache.stats();
+    }
+
+    /// Drops cached expressions that refer to `name`.
+    pub fn invalidate_cached(&mut self, name: &str) -> usize {
+        return self.cache.invalidate(name);
+    }
+
+    pub fn clear_cache(&mut self) {
+        self.cache.clear();
+    }
+
+    /// Forgets every variable, function, recorded result and cached
+    /// expression and goes back to the default float policy, angle unit and
+    /// number format. Limits, the cache capacity and the subscriber are kept.
+    pub fn reset(&mut self) {
+        *self.parser.registry_mut() = Registry::new();
+        self.set_float_policy(FloatPolicy::default());
+        self.set_angle_unit(AngleUnit::default());
+        self.number_format = NumberFormat::default();
+        self.cache.clear();
+    }
+
+    /// Sends lexer, parser and evaluator events at `level` or more important
+    /// to `subscriber`. Nothing is reported until this is called.
+    pub fn set_subscriber<F>(&mut self, level: Level, subscriber: F)
+    where
+        F: Fn(&Event) + Send + Sync + 'static,
+    {
+        let tracer = Tracer::new(level, Arc::new(subscriber));
+
+        self.lexer.set_tracer(tracer.clone());
+        self.parser.set_tracer(tracer);
+    }
+
+    pub fn clear_subscriber(&mut self) {
+        self.lexer.set_tracer(Tracer::default());
+        self.parser.set_tracer(Tracer::default());
+    }
+
+    /// The names expressions can refer to, as used for suggestions and
+    /// completion.
+    pub fn registry(&self) -> &Registry {
+        return self.parser.registry();
+    }
+
+    pub fn variable(&self, name: &str) -> Option<f32> {
+        return self.parser.registry().variable(name);
+    }
+
+    pub fn set_variable(&mut self, name: &str, value: f32) -> Result<(), ParseError> {
+        return self
+            .parser
+            .registry_mut()
+            .set_variable(name, value)
+            .map_err(ParseError::InvalidIdentifier);
+    }
+
+    pub fn remove_variable(&mut self, name: &str) -> Option<f32> {
+        return self.parser.registry_mut().remove_variable(name);
+    }
+
+    /// Keeps `value` as `ans` and as `$n` for later expressions, returning
+    /// `n`. The console records every result it prints.
+    pub fn record_result(&mut self, value: f32) -> usize {
+        return self.parser.registry_mut().record(value);
+    }
+
+    pub fn results(&self) -> &[f32] {
+        return self.parser.registry().results();
+    }
+
+    /// Warnings produced by the last call to `interpret` under `FloatPolicy::Warn`.
+    pub fn warnings(&self) -> &[ParseError] {
+        return self.parser.warnings();
+    }
+
+    /// Evaluates a single expression. Input with several statements is run
+    /// like `run` does, and the value of the last one is returned.
     pub fn interpret(&mut self, expression: &'a str) -> Result<f32, ParseError> {
-        if expression.is_empty() {
+        return self.run(expression)?.ok_or(ParseError::NoValue);
+    }
+
+    /// Runs statements separated by `;` or by line breaks outside
+    /// parentheses. A statement is an expression, an assignment `x = 1` or a
+    /// function definition `f(x, y) = x * y`. Returns the value of the last
+    /// statement, or `None` if it defines a function.
+    pub fn run(&mut self, source: &'a str) -> Result<Option<f32>, ParseError> {
+        let deadline = Deadline::start(&self.limits);
+        let key = (self.cache.capacity() > 0).then(|| ExpressionCache::normalize(source));
+
+        if let Some(compiled) = key.as_deref().and_then(|key| self.cache.get(key)) {
+            let budget = Budget::new(&self.limits, &deadline);
+            let result = self.parser.execute_within(compiled, budget)?;
+            deadline.check()?;
+
+            return Ok(Some(result));
+        }
+
+        if source.is_empty() {
             return Err(ParseError::ExpressionEmpty);
         }
-        let token_stream = self.lexer.lex(expression);
+        self.limits
+            .check_input(source)
+            .map_err(ParseError::LimitExceeded)?;
+
+        let token_stream = self.lexer.lex(source)?;
+        self.limits
+            .check_tokens(&token_stream)
+            .map_err(|(limit, _)| ParseError::LimitExceeded(limit))?;
+        deadline.check()?;
+
+        let statements = statement::split(token_stream);
+        // only a lone expression is cached, so a hit never skips an
+        // assignment or a definition
+        let lone = statements.len() == 1;
+        let mut value = Err(ParseError::ExpressionEmpty);
+
+        for tokens in statements {
+            let statement = Statement::classify(tokens, source).map_err(Diagnostic::into_error)?;
+
+            value = match statement {
+                Statement::Expression(tokens) => {
+                    let compiled = self.compile_tokens(source, &tokens, &deadline)?;
+                    let result = self.evaluate_within(&compiled, &deadline);
+
+                    if let (true, Some(key)) = (lone, &key) {
+                        self.cache.insert(key.clone(), compiled);
+                    }
+
+                    Ok(Some(result?))
+                }
+                Statement::Assignment(name, tokens) => {
+                    let compiled = self.compile_tokens(source, &tokens, &deadline)?;
+                    let result = self.evaluate_within(&compiled, &deadline)?;
+                    self.set_variable(&name, result)?;
+
+                    Ok(Some(result))
+                }
+                Statement::Definition(function) => {
+                    self.define_function(function, &deadline)?;
+
+                    Ok(None)
+                }
+            };
+        }
+
+        return value;
+    }
+
+    /// Checks that `function` can be called and stores it. The body may
+    /// call functions defined before it, but not itself, not even through
+    /// another function.
+    fn define_function(
+        &mut self,
+        function: Function,
+        deadline: &Deadline,
+    ) -> Result<(), ParseError> {
+        if statement::calls(function.body(), function.name()) {
+            return Err(ParseError::RecursiveFunction(function.name().to_string()));
+        }
+
+        let body = statement::expand_body(&function, self.registry(), &self.limits, deadline)
+            .map_err(Diagnostic::into_error)?;
+        let token_queue = parse_tokens(&mut self.parser, &self.limits, &body, deadline)?;
+        CompiledExpr::new(function.source(), token_queue, self.float_policy())?;
+
+        self.parser
+            .registry_mut()
+            .define_function(function)
+            .map_err(ParseError::InvalidIdentifier)?;
+        // cached expressions may call an older definition
+        self.cache.clear();
+
+        return Ok(());
+    }
+
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        return self.parser.registry().function(name);
+    }
+
+    pub fn remove_function(&mut self, name: &str) -> Option<Function> {
+        self.cache.clear();
+
+        return self.parser.registry_mut().remove_function(name);
+    }
+
+    fn parse(
+        &mut self,
+        expression: &'a str,
+        deadline: &Deadline,
+    ) -> Result<VecDeque<Token>, ParseError> {
+        return parse_with(
+            &mut self.lexer,
+            &mut self.parser,
+            &self.limits,
+            expression,
+            deadline,
+        );
+    }
+
+    /// Lexes and parses `expression` once so it can be evaluated many times.
+    /// The result keeps the current float policy.
+    pub fn compile(&mut self, expression: &'a str) -> Result<CompiledExpr, ParseError> {
+        let deadline = Deadline::start(&self.limits);
+
+        return self.compile_within(expression, &deadline);
+    }
+
+    fn compile_within(
+        &mut self,
+        expression: &'a str,
+        deadline: &Deadline,
+    ) -> Result<CompiledExpr, ParseError> {
+        let token_queue = self.parse(expression, deadline)?;
+
+        return self.build(expression, token_queue, deadline);
+    }
+
+    /// Compiles one statement of `source`.
+    fn compile_tokens(
+        &mut self,
+        source: &str,
+        tokens: &[Token],
+        deadline: &Deadline,
+    ) -> Result<CompiledExpr, ParseError> {
+        let token_queue = parse_tokens(&mut self.parser, &self.limits, tokens, deadline)?;
+        let text = statement::text(source, statement::span_of(tokens));
+
+        return self.build(text, token_queue, deadline);
+    }
+
+    fn build(
+        &self,
+        source: &str,
+        token_queue: VecDeque<Token>,
+        deadline: &Deadline,
+    ) -> Result<CompiledExpr, ParseError> {
+        let mut compiled = CompiledExpr::new(source, token_queue, self.float_policy())?;
+
+        if self.optimize {
+            compiled = compiled.optimized();
+        }
+        deadline.check()?;
+
+        return Ok(compiled);
+    }
+
+    /// Evaluates a compiled expression with the variables, float policy and
+    /// subscriber of this interpreter instead of explicit bindings.
+    pub fn evaluate(&mut self, expression: &CompiledExpr) -> Result<f32, ParseError> {
+        let deadline = Deadline::start(&self.limits);
+
+        return self.evaluate_within(expression, &deadline);
+    }
+
+    fn evaluate_within(
+        &mut self,
+        expression: &CompiledExpr,
+        deadline: &Deadline,
+    ) -> Result<f32, ParseError> {
+        let budget = Budget::new(&self.limits, deadline);
+        let result = self.parser.execute_within(expression, budget)?;
+        deadline.check()?;
+
+        return Ok(result);
+    }
+
+    /// Runs `source` like `run` would, but on a copy of the variables and
+    /// functions and without reporting to the subscriber or touching the
+    /// cache, so a result can be shown while the input is still being typed.
+    /// The copy shares the registry until `source` assigns or defines
+    /// something, and runs within `Limits::PREVIEW` on top of the limits set.
+    pub fn preview(&self, source: &str) -> Result<Option<f32>, ParseError> {
+        let mut scratch = Interpreter::new();
+        *scratch.parser.registry_mut() = self.registry().clone();
+        scratch.parser.set_float_policy(self.float_policy());
+        scratch.parser.set_angle_unit(self.angle_unit());
+        scratch.optimize = self.optimize;
+        scratch.limits = self.limits.tighter(&Limits::PREVIEW);
+
+        return scratch.run(source);
+    }
+
+    /// Checks `expression` without evaluating it and reports every problem
+    /// found, along with as much of the tree as could be recovered.
+    pub fn diagnose(&mut self, expression: &'a str) -> (Option<Expr>, Vec<Diagnostic>) {
+        let deadline = Deadline::start(&self.limits);
+        if let Err(limit) = self.limits.check_input(expression) {
+            let span = Span::new(0, expression.chars().count());
+            return (
+                None,
+                vec![Diagnostic::new(ParseError::LimitExceeded(limit), span)],
+            );
+        }
+
+        let (token_stream, mut diagnostics) = self.lexer.lex_all(expression);
+
+        if let Err((limit, span)) = self.limits.check_tokens(&token_stream) {
+            diagnostics.push(Diagnostic::new(ParseError::LimitExceeded(limit), span));
+            diagnostics.sort_by_key(|diagnostic| diagnostic.span().start);
+            return (None, diagnostics);
+        }
+
+        // nothing left to parse, an empty-expression error would only be noise
+        if token_stream.is_empty() && !diagnostics.is_empty() {
+            return (None, diagnostics);
+        }
+
+        let token_stream = match statement::expand(
+            &token_stream,
+            self.parser.registry(),
+            &self.limits,
+            &deadline,
+        ) {
+            Ok(expanded) => expanded,
+            Err(diagnostic) => {
+                diagnostics.push(diagnostic);
+                diagnostics.sort_by_key(|diagnostic| diagnostic.span().start);
+                return (None, diagnostics);
+            }
+        };
+
+        let (tree, parse_diagnostics) = self.parser.parse_recovering(token_stream);
 
-        //println!("{:?}", token_stream);
-        let token_queue = self.parser.parse(token_stream)?;
+        diagnostics.extend(parse_diagnostics);
+        diagnostics.sort_by_key(|diagnostic| diagnostic.span().start);
 
-        return Ok(self.parser.execute(token_queue))?;
+        return (tree, diagnostics);
     }
 }
@@ -0,0 +1,399 @@
+#![allow(clippy::needless_return)]
+
+use alloc::{format, string::ToString, vec::Vec};
+use cranelift_codegen::{
+    ir::{condcodes::FloatCC, types, AbiParam, FuncRef, InstBuilder, MemFlags, Signature, Value},
+    settings::{self, Configurable},
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, FuncId, Linkage, Module};
+
+use super::{
+    bytecode::Instruction,
+    compiled::CompiledExpr,
+    evaluator::Evaluation,
+    lexer::TokenKind,
+    parser::{FloatPolicy, ParseError},
+};
+
+/// Signature of the generated code: the bindings, and a flag set to non-zero
+/// when the float policy has to be consulted.
+type NativeFn = unsafe extern "C" fn(*const f64, *mut u8) -> f64;
+
+/// Built-ins the generated code calls back into. The functions set `flag`
+/// when their argument is out of domain or their result is not finite.
+const HELPERS: &[(&str, *const u8)] = &[
+    ("arth_sin", checked_sin as *const u8),
+    ("arth_cos", checked_cos as *const u8),
+    ("arth_tan", checked_tan as *const u8),
+    ("arth_log", checked_log as *const u8),
+    ("arth_pow", pow as *const u8),
+];
+
+/// The smallest `f32` above zero. Smaller results are taken to underflow.
+const F32_TINY: f64 = f32::from_bits(1) as f64;
+
+/// Whether `value` is the same kind of number as an `f32`: not NaN, not
+/// beyond `f32::MAX` and not so small it would be zero.
+fn fits_f32(value: f64) -> bool {
+    let magnitude = value.abs();
+
+    return magnitude <= f64::from(f32::MAX) && (magnitude == 0.0 || magnitude >= F32_TINY);
+}
+
+/// `Evaluation::in_domain`, both in double precision and for the argument
+/// narrowed to `f32`, as the interpreter would see it.
+fn in_domain(kind: &TokenKind, arg: f64) -> bool {
+    let in_f64 = match kind {
+        TokenKind::Log => arg > 0.0,
+        TokenKind::Tan => arg.is_finite() && arg.cos().abs() > f64::EPSILON,
+        TokenKind::Sin | TokenKind::Cos => arg.is_finite(),
+        _ => true,
+    };
+
+    return in_f64 && Evaluation::in_domain(kind, arg as f32);
+}
+
+fn checked(kind: &TokenKind, arg: f64, value: f64, flag: &mut u8) -> f64 {
+    if !in_domain(kind, arg) || !fits_f32(value) {
+        *flag = 1;
+    }
+
+    return value;
+}
+
+extern "C" fn checked_sin(arg: f64, flag: &mut u8) -> f64 {
+    return checked(&TokenKind::Sin, arg, arg.sin(), flag);
+}
+
+extern "C" fn checked_cos(arg: f64, flag: &mut u8) -> f64 {
+    return checked(&TokenKind::Cos, arg, arg.cos(), flag);
+}
+
+extern "C" fn checked_tan(arg: f64, flag: &mut u8) -> f64 {
+    return checked(&TokenKind::Tan, arg, arg.tan(), flag);
+}
+
+extern "C" fn checked_log(arg: f64, flag: &mut u8) -> f64 {
+    return checked(&TokenKind::Log, arg, arg.log10(), flag);
+}
+
+extern "C" fn pow(x: f64, y: f64) -> f64 {
+    return x.powf(y);
+}
+
+/// Owns the executable memory `function` points into.
+struct Native {
+    module: Option<JITModule>,
+    function: NativeFn,
+}
+
+// SAFETY: `JITModule` is not `Sync` because defining functions mutates it,
+// but the module here is finished and only touched again by `drop`, which
+// has `&mut self`. Through `&Native` all that happens is calling `function`,
+// which reads its arguments and writes only the flag its caller passes in, so
+// calls from several threads at once share nothing mutable.
+unsafe impl Sync for Native {}
+
+impl Drop for Native {
+    fn drop(&mut self) {
+        if let Some(module) = self.module.take() {
+            // SAFETY: `function` is the only pointer into the module and it
+            // is dropped along with it.
+            unsafe { module.free_memory() };
+        }
+    }
+}
+
+/// A `CompiledExpr` lowered to native code by `CompiledExpr::jit`.
+///
+/// The native code computes in `f64`, starting from the `f32` constants of
+/// the bytecode. Unless the float policy is `Ieee`, any result that `f32`
+/// could not hold (a non-finite value, one beyond `f32::MAX`, one that would
+/// underflow to zero) or a function outside its domain makes it run the
+/// bytecode instead, on the bindings narrowed to `f32`, and so do bindings
+/// that do not fit in `f32`. Overflow is then reported as `CompiledExpr::eval`
+/// reports it, a binding too large for `f32` counting as one. What is not
+/// caught is an intermediate result that `f32` rounding alone would turn into
+/// a zero divisor or a pole, so near those the errors can still differ from
+/// the interpreter's. Under `Ieee` the results are the `f64` ones. Without a
+/// Cranelift backend for the host everything runs on the bytecode.
+pub struct JitExpr {
+    compiled: CompiledExpr,
+    native: Option<Native>,
+}
+
+impl core::fmt::Debug for JitExpr {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("JitExpr")
+            .field("compiled", &self.compiled)
+            .field("native", &self.native.is_some())
+            .finish()
+    }
+}
+
+impl CompiledExpr {
+    /// Generates native code for the expression, falling back to the
+    /// bytecode if that is not possible on this host.
+    pub fn jit(&self) -> JitExpr {
+        return JitExpr {
+            compiled: self.clone(),
+            native: lower(self),
+        };
+    }
+}
+
+impl JitExpr {
+    pub fn compiled(&self) -> &CompiledExpr {
+        return &self.compiled;
+    }
+
+    /// Whether `eval` runs native code rather than the bytecode.
+    pub fn is_native(&self) -> bool {
+        return self.native.is_some();
+    }
+
+    /// Same contract as `CompiledExpr::eval`, in double precision.
+    pub fn eval(&self, bindings: &[f64]) -> Result<f64, ParseError> {
+        return self.eval_with_warnings(bindings, &mut Vec::new());
+    }
+
+    pub fn eval_with_warnings(
+        &self,
+        bindings: &[f64],
+        warnings: &mut Vec<ParseError>,
+    ) -> Result<f64, ParseError> {
+        let checked = self.compiled.float_policy() != FloatPolicy::Ieee;
+        let Some(native) = &self.native else {
+            return self.interpret(bindings, warnings);
+        };
+
+        if let Some(unbound) = self.compiled.variables().get(bindings.len()) {
+            return Err(ParseError::UnboundVariable(unbound.to_string()));
+        }
+        if checked && !bindings.iter().all(|&binding| fits_f32(binding)) {
+            return self.interpret(bindings, warnings);
+        }
+
+        let mut flag = 0u8;
+        // SAFETY: the code only reads a binding per variable, which was
+        // checked above, and writes nothing but `flag`.
+        let value = unsafe { (native.function)(bindings.as_ptr(), &mut flag) };
+
+        if flag != 0 && checked {
+            return self.interpret(bindings, warnings);
+        }
+
+        return Ok(value);
+    }
+
+    /// Runs the bytecode on the bindings narrowed to `f32`, a finite binding
+    /// that overflows counting as a non-finite result.
+    fn interpret(
+        &self,
+        bindings: &[f64],
+        warnings: &mut Vec<ParseError>,
+    ) -> Result<f64, ParseError> {
+        let mut narrowed = Vec::with_capacity(bindings.len());
+
+        for &binding in bindings {
+            let value = binding as f32;
+
+            if binding.is_finite() && value.is_infinite() {
+                let issue = ParseError::NonFinite(format!("{binding:?}"));
+
+                match self.compiled.float_policy() {
+                    FloatPolicy::Strict => return Err(issue),
+                    FloatPolicy::Ieee => {}
+                    FloatPolicy::Warn => warnings.push(issue),
+                }
+            }
+            narrowed.push(value);
+        }
+
+        return self
+            .compiled
+            .eval_with_warnings(&narrowed, warnings)
+            .map(f64::from);
+    }
+}
+
+fn lower(compiled: &CompiledExpr) -> Option<Native> {
+    let mut flags = settings::builder();
+    flags.set("opt_level", "speed").ok()?;
+    flags.set("use_colocated_libcalls", "false").ok()?;
+    flags.set("is_pic", "false").ok()?;
+
+    let isa = cranelift_native::builder()
+        .ok()?
+        .finish(settings::Flags::new(flags))
+        .ok()?;
+
+    let mut builder = JITBuilder::with_isa(isa, default_libcall_names());
+    for (name, helper) in HELPERS {
+        builder.symbol(*name, *helper);
+    }
+
+    let mut module = JITModule::new(builder);
+    let function = match define(&mut module, compiled) {
+        Some(id) => module.get_finalized_function(id),
+        None => {
+            // SAFETY: nothing was handed out from the module.
+            unsafe { module.free_memory() };
+            return None;
+        }
+    };
+
+    return Some(Native {
+        module: Some(module),
+        // SAFETY: `define` built the function with the `NativeFn` signature.
+        function: unsafe { core::mem::transmute::<*const u8, NativeFn>(function) },
+    });
+}
+
+fn define(module: &mut JITModule, compiled: &CompiledExpr) -> Option<FuncId> {
+    let pointer = module.target_config().pointer_type();
+    let checked = compiled.float_policy() != FloatPolicy::Ieee;
+
+    let mut unary = module.make_signature();
+    unary.params.push(AbiParam::new(types::F64));
+    unary.params.push(AbiParam::new(pointer));
+    unary.returns.push(AbiParam::new(types::F64));
+
+    let mut binary = module.make_signature();
+    binary.params.push(AbiParam::new(types::F64));
+    binary.params.push(AbiParam::new(types::F64));
+    binary.returns.push(AbiParam::new(types::F64));
+
+    let mut helpers = Vec::new();
+    for (name, _) in HELPERS {
+        let signature: &Signature = if *name == "arth_pow" { &binary } else { &unary };
+        helpers.push(
+            module
+                .declare_function(name, Linkage::Import, signature)
+                .ok()?,
+        );
+    }
+
+    let mut context = module.make_context();
+    context.func.signature.params.push(AbiParam::new(pointer));
+    context.func.signature.params.push(AbiParam::new(pointer));
+    context
+        .func
+        .signature
+        .returns
+        .push(AbiParam::new(types::F64));
+
+    let id = module
+        .declare_anonymous_function(&context.func.signature)
+        .ok()?;
+
+    let mut function_context = FunctionBuilderContext::new();
+    let mut b = FunctionBuilder::new(&mut context.func, &mut function_context);
+
+    let helpers: Vec<FuncRef> = helpers
+        .into_iter()
+        .map(|helper| module.declare_func_in_func(helper, b.func))
+        .collect();
+    let [sin, cos, tan, log, pow] = helpers[..] else {
+        return None;
+    };
+
+    let entry = b.create_block();
+    b.append_block_params_for_function_params(entry);
+    b.switch_to_block(entry);
+    b.seal_block(entry);
+
+    let bindings = b.block_params(entry)[0];
+    let flag = b.block_params(entry)[1];
+    let largest = b.ins().f64const(f64::from(f32::MAX));
+    let smallest = b.ins().f64const(F32_TINY);
+    let zero = b.ins().f64const(0.0);
+    let mut not_fitting = b.ins().iconst(types::I8, 0);
+    let mut stack: Vec<Value> = Vec::new();
+    let mut temporaries: Vec<Value> = Vec::new();
+
+    for instruction in compiled.program().instructions() {
+        let value = match *instruction {
+            Instruction::Const(value) => b.ins().f64const(f64::from(value)),
+            Instruction::Load(slot) => {
+                let offset = i32::try_from(slot as usize * size_of::<f64>()).ok()?;
+                b.ins()
+                    .load(types::F64, MemFlags::trusted(), bindings, offset)
+            }
+            Instruction::Save(temporary) => {
+                let value = *stack.last()?;
+                let temporary = temporary as usize;
+                if temporaries.len() <= temporary {
+                    temporaries.resize(temporary + 1, value);
+                }
+                temporaries[temporary] = value;
+                continue;
+            }
+            Instruction::Recall(temporary) => *temporaries.get(temporary as usize)?,
+            Instruction::Sin | Instruction::Cos | Instruction::Tan | Instruction::Log => {
+                let arg = stack.pop()?;
+                let helper = match instruction {
+                    Instruction::Sin => sin,
+                    Instruction::Cos => cos,
+                    Instruction::Tan => tan,
+                    _ => log,
+                };
+
+                let call = b.ins().call(helper, &[arg, flag]);
+                b.inst_results(call)[0]
+            }
+            binary => {
+                let lhs = stack.pop()?;
+                let rhs = stack.pop()?;
+
+                let value = match binary {
+                    Instruction::Add => b.ins().fadd(rhs, lhs),
+                    Instruction::Sub => b.ins().fsub(rhs, lhs),
+                    Instruction::Mul => b.ins().fmul(rhs, lhs),
+                    Instruction::Div => b.ins().fdiv(rhs, lhs),
+                    _ => {
+                        let call = b.ins().call(pow, &[rhs, lhs]);
+                        b.inst_results(call)[0]
+                    }
+                };
+
+                // `fits_f32`: NaN compares unordered, and a result `f32`
+                // would round to zero is above zero but below the smallest
+                if checked {
+                    let magnitude = b.ins().fabs(value);
+                    let overflow =
+                        b.ins()
+                            .fcmp(FloatCC::UnorderedOrGreaterThan, magnitude, largest);
+                    let nonzero = b.ins().fcmp(FloatCC::GreaterThan, magnitude, zero);
+                    let tiny = b.ins().fcmp(FloatCC::LessThan, magnitude, smallest);
+                    let underflow = b.ins().band(nonzero, tiny);
+                    let unfit = b.ins().bor(overflow, underflow);
+                    not_fitting = b.ins().bor(not_fitting, unfit);
+                }
+
+                value
+            }
+        };
+
+        stack.push(value);
+    }
+
+    let result = match stack.pop() {
+        Some(result) => result,
+        None => b.ins().f64const(0.0),
+    };
+
+    let previous = b.ins().load(types::I8, MemFlags::trusted(), flag, 0);
+    let raised = b.ins().bor(previous, not_fitting);
+    b.ins().store(MemFlags::trusted(), raised, flag, 0);
+    b.ins().return_(&[result]);
+    b.finalize();
+
+    module.define_function(id, &mut context).ok()?;
+    module.clear_context(&mut context);
+    module.finalize_definitions().ok()?;
+
+    return Some(id);
+}
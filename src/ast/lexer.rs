@@ -1,9 +1,18 @@
 #![allow(clippy::needless_return)]
 
-use core::fmt;
-use std::{f32::consts::PI, iter::Peekable, str::Chars};
-
-use colored::Colorize;
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::{f32::consts::PI, fmt, iter::Peekable, str::Chars};
+
+use super::{
+    parser::{Diagnostic, ParseError},
+    registry,
+    trace::{Level, Stage, Tracer},
+};
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum TokenKind {
@@ -22,6 +31,15 @@ pub enum TokenKind {
 
     OpenParen,
     CloseParen,
+
+    /// `=` in an assignment or function definition.
+    Equals,
+    /// Separates the parameters and arguments of user functions.
+    Comma,
+    /// Ends a statement.
+    Semicolon,
+    /// Ends a statement, unless inside parentheses.
+    Newline,
 }
 
 impl fmt::Display for TokenKind {
@@ -41,6 +59,10 @@ impl fmt::Display for TokenKind {
             TokenKind::OpenParen => "(".to_string(),
             TokenKind::CloseParen => ")".to_string(),
             TokenKind::Log => "Log ".to_string(),
+            TokenKind::Equals => " = ".to_string(),
+            TokenKind::Comma => ", ".to_string(),
+            TokenKind::Semicolon => "; ".to_string(),
+            TokenKind::Newline => "\n".to_string(),
         };
 
         write!(f, "{}", s)
@@ -68,6 +90,10 @@ impl TokenKind {
         )
     }
 
+    pub fn is_identifier(&self) -> bool {
+        matches!(self, TokenKind::Identifier(_))
+    }
+
     pub fn is_open_paren(&self) -> bool {
         matches!(self, TokenKind::OpenParen)
     }
@@ -76,38 +102,72 @@ impl TokenKind {
         matches!(self, TokenKind::CloseParen)
     }
 
-    pub fn precedence(&self) -> usize {
+    /// Returns `None` for tokens that are not operators.
+    pub fn precedence(&self) -> Option<usize> {
         // ( / ) > * > / > + / -
-        match self {
+        let precedence = match self {
             TokenKind::Plus | TokenKind::Minus => 1,
             TokenKind::Slash | TokenKind::Asterix => 2,
             TokenKind::OpenParen | TokenKind::CloseParen | TokenKind::Power => 3,
             TokenKind::Sin | TokenKind::Cos | TokenKind::Tan | TokenKind::Log => 4,
             TokenKind::Pi => 5,
-            _ => unreachable!("Unknown operator"),
-        }
+            _ => return None,
+        };
+
+        return Some(precedence);
+    }
+}
+
+/// Half-open range of character offsets into the lexed input.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        return Self { start, end };
+    }
+
+    /// Smallest span covering both `self` and `other`.
+    pub fn to(&self, other: Span) -> Span {
+        return Span::new(self.start.min(other.start), self.end.max(other.end));
     }
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Token {
     kind: TokenKind,
+    span: Span,
 }
 
 impl Token {
     pub fn new(kind: TokenKind) -> Self {
-        return Self { kind };
+        return Self {
+            kind,
+            span: Span::default(),
+        };
+    }
+
+    pub fn spanned(kind: TokenKind, span: Span) -> Self {
+        return Self { kind, span };
     }
 
     pub fn kind(&self) -> &TokenKind {
         return &self.kind;
     }
+
+    pub fn span(&self) -> Span {
+        return self.span;
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Lexer {
     current_pos: usize,
     len: usize,
+    tracer: Tracer,
 }
 
 impl<'a> Lexer {
@@ -115,9 +175,14 @@ impl<'a> Lexer {
         Self {
             current_pos: 0,
             len: 0,
+            tracer: Tracer::default(),
         }
     }
 
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = tracer;
+    }
+
     fn collect_until<F>(
         &mut self,
         current_char: char,
@@ -129,81 +194,82 @@ impl<'a> Lexer {
     {
         let mut buffer = String::from(current_char);
 
-        let mut next = self.next_char(input);
-
-        while let Some(chr) = next {
+        while let Some(chr) = self.peek(input) {
             if f(chr) {
                 break;
             }
 
             buffer.push(chr);
-
-            if let Some(peeked_chr) = self.peek(input) {
-                if f(peeked_chr) {
-                    break;
-                }
-            }
-
-            next = self.next_char(input);
+            self.next_char(input);
         }
 
         return buffer;
     }
 
-    fn skip_while(&mut self, input: &mut Peekable<Chars<'a>>, target: char) -> Option<()> {
-        if self.peek(input)? == target {
-            let mut next = self.next_char(input)?;
-            while next == target {
-                if self.peek(input)? != target {
-                    break;
-                }
-                next = self.next_char(input)?;
-            }
+    fn skip_while<F>(&mut self, input: &mut Peekable<Chars<'a>>, skip: F)
+    where
+        F: Fn(char) -> bool,
+    {
+        while self.peek(input).is_some_and(&skip) {
+            self.next_char(input);
         }
+    }
 
-        return Some(());
+    fn token_from(&self, kind: TokenKind, start: usize) -> Token {
+        return Token::spanned(kind, Span::new(start, self.current_pos));
+    }
+
+    fn parse_number(&self, number: String, start: usize) -> Result<Option<Token>, Diagnostic> {
+        return match number.parse::<f32>() {
+            Ok(value) => Ok(Some(self.token_from(TokenKind::Number(value), start))),
+            Err(_) => Err(Diagnostic::new(
+                ParseError::InvalidNumber(number),
+                Span::new(start, self.current_pos),
+            )),
+        };
     }
 
-    fn next_token(&mut self, input: &mut Peekable<Chars<'a>>) -> Option<Token> {
+    fn next_token(&mut self, input: &mut Peekable<Chars<'a>>) -> Result<Option<Token>, Diagnostic> {
         if self.current_pos >= self.len {
-            return None;
+            return Ok(None);
         }
 
-        self.skip_while(input, ' ');
+        // line breaks separate statements, so they are tokens
+        self.skip_while(input, |chr| chr.is_whitespace() && chr != '\n');
+
+        let start = self.current_pos;
 
-        let current_char = self.next_char(input)?;
+        let Some(current_char) = self.next_char(input) else {
+            return Ok(None);
+        };
 
         if current_char.is_numeric() {
             let number = String::from(current_char);
 
             if let Some(peeked_chr) = self.peek(input) {
                 if peeked_chr != '.' && !peeked_chr.is_numeric() {
-                    return Some(Token::new(TokenKind::Number(
-                        number.parse::<f32>().unwrap(),
-                    )));
+                    return self.parse_number(number, start);
                 }
             }
 
             let number = self.collect_until(current_char, |x| x != '.' && !x.is_numeric(), input);
 
-            return Some(Token::new(TokenKind::Number(
-                number.parse::<f32>().unwrap(),
-            )));
+            return self.parse_number(number, start);
         }
 
         if current_char.is_alphabetic() {
             let alpha = self.collect_until(current_char, |x| !x.is_alphabetic(), input);
 
-            let math_fn = match alpha.to_lowercase().as_str() {
-                "sin" => TokenKind::Sin,
-                "cos" => TokenKind::Cos,
-                "tan" => TokenKind::Tan,
-                "pi" => TokenKind::Pi,
-                "log" => TokenKind::Log,
-                identifier => TokenKind::Identifier(identifier.to_string()),
-            };
+            let math_fn = registry::builtin(&alpha).unwrap_or(TokenKind::Identifier(alpha));
 
-            return Some(Token::new(math_fn));
+            return Ok(Some(self.token_from(math_fn, start)));
+        }
+
+        // `$n` refers to the n-th recorded result
+        if current_char == '$' && self.peek(input).is_some_and(|chr| chr.is_ascii_digit()) {
+            let result = self.collect_until(current_char, |x| !x.is_ascii_digit(), input);
+
+            return Ok(Some(self.token_from(TokenKind::Identifier(result), start)));
         }
 
         let mut operator: Option<TokenKind> = None;
@@ -216,28 +282,21 @@ impl<'a> Lexer {
             '^' => operator = Some(TokenKind::Power),
             '(' => operator = Some(TokenKind::OpenParen),
             ')' => operator = Some(TokenKind::CloseParen),
-            unknown => {
-                if !unknown.is_alphabetic() {
-                    println!(
-                        "{}",
-                        format!(
-                            "{}: Unknown symbol: `{}`",
-                            "Warning".to_string().underline(),
-                            format!("{unknown}").bold()
-                        )
-                        .yellow()
-                    )
-                }
-            }
+            '=' => operator = Some(TokenKind::Equals),
+            ',' => operator = Some(TokenKind::Comma),
+            ';' => operator = Some(TokenKind::Semicolon),
+            '\n' => operator = Some(TokenKind::Newline),
+            _ => {}
         };
 
-        if let Some(op) = operator {
-            return Some(Token::new(op));
-        }
-
-        //Variable area
+        let Some(op) = operator else {
+            return Err(Diagnostic::new(
+                ParseError::InvalidSymbol(current_char),
+                Span::new(start, self.current_pos),
+            ));
+        };
 
-        return None;
+        return Ok(Some(self.token_from(op, start)));
     }
 
     fn peek(&mut self, input: &mut Peekable<Chars<'a>>) -> Option<char> {
@@ -256,18 +315,39 @@ impl<'a> Lexer {
         return next;
     }
 
-    pub fn lex(&mut self, input: &'a str) -> Vec<Token> {
+    pub fn lex(&mut self, input: &'a str) -> Result<Vec<Token>, ParseError> {
+        let (token_stream, diagnostics) = self.lex_all(input);
+
+        if let Some(diagnostic) = diagnostics.into_iter().next() {
+            return Err(diagnostic.into_error());
+        }
+
+        return Ok(token_stream);
+    }
+
+    /// Lexes the whole input, skipping over malformed tokens instead of
+    /// stopping at the first one.
+    pub fn lex_all(&mut self, input: &'a str) -> (Vec<Token>, Vec<Diagnostic>) {
         let mut iter = input.chars().peekable();
         let mut token_stream = vec![];
+        let mut diagnostics = vec![];
+        self.current_pos = 0;
         self.len = input.len();
 
-        while let Some(token) = self.next_token(&mut iter) {
-            token_stream.push(token)
+        loop {
+            match self.next_token(&mut iter) {
+                Ok(Some(token)) => token_stream.push(token),
+                Ok(None) => break,
+                Err(diagnostic) => diagnostics.push(diagnostic),
+            }
         }
 
         self.current_pos = 0;
 
-        return token_stream;
+        self.tracer
+            .emit(Level::Debug, Stage::Lexer, || format!("{token_stream:?}"));
+
+        return (token_stream, diagnostics);
     }
     /*
     pub fn collect(&mut self) -> Vec<Token> {
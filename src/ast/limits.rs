@@ -0,0 +1,217 @@
+#![allow(clippy::needless_return)]
+
+use core::fmt;
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+use super::{
+    lexer::{Span, Token, TokenKind},
+    parser::ParseError,
+};
+
+/// Evaluation steps between two checks of the deadline.
+pub const DEADLINE_INTERVAL: usize = 256;
+
+/// The bound from `Limits` that an input went over, with its value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    /// Characters in the source.
+    InputLength(usize),
+    Tokens(usize),
+    /// Nesting of parentheses.
+    Depth(usize),
+    /// Instructions or tokens the evaluator runs, counted as it runs them.
+    Steps(usize),
+    /// User functions calling each other.
+    CallDepth(usize),
+    /// Time spent on one call, checked between lexing, parsing, compiling
+    /// and evaluating, and every `DEADLINE_INTERVAL` evaluation steps.
+    #[cfg(feature = "std")]
+    Time(Duration),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::InputLength(max) => write!(f, "longer than {max} characters"),
+            Limit::Tokens(max) => write!(f, "more than {max} tokens"),
+            Limit::Depth(max) => write!(f, "nested deeper than {max} levels"),
+            Limit::Steps(max) => write!(f, "more than {max} evaluation steps"),
+            Limit::CallDepth(max) => write!(f, "calling functions deeper than {max} levels"),
+            #[cfg(feature = "std")]
+            Limit::Time(max) => write!(f, "took longer than {max:?}"),
+        }
+    }
+}
+
+/// Bounds on the work `Interpreter` does for one expression, for input
+/// that cannot be trusted. Every limit is off by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Limits {
+    pub max_input_length: Option<usize>,
+    pub max_tokens: Option<usize>,
+    /// How deeply parentheses may nest.
+    pub max_depth: Option<usize>,
+    /// How many instructions one evaluation may run.
+    pub max_steps: Option<usize>,
+    /// How deeply user functions may call each other.
+    pub max_call_depth: Option<usize>,
+    #[cfg(feature = "std")]
+    pub timeout: Option<Duration>,
+}
+
+fn exceeds(value: usize, max: Option<usize>) -> Option<usize> {
+    return max.filter(|max| value > *max);
+}
+
+impl Limits {
+    /// Bounds for input that is evaluated while it is still being typed,
+    /// small enough to run on every keystroke.
+    pub const PREVIEW: Limits = Limits {
+        max_input_length: Some(256),
+        max_tokens: Some(128),
+        max_depth: Some(32),
+        max_steps: Some(4_096),
+        max_call_depth: Some(8),
+        #[cfg(feature = "std")]
+        timeout: Some(Duration::from_millis(5)),
+    };
+
+    /// Each limit of `self` or `other`, whichever is tighter.
+    pub fn tighter(&self, other: &Limits) -> Limits {
+        let min = |lhs: Option<usize>, rhs: Option<usize>| match (lhs, rhs) {
+            (Some(lhs), Some(rhs)) => Some(lhs.min(rhs)),
+            (lhs, rhs) => lhs.or(rhs),
+        };
+
+        return Limits {
+            max_input_length: min(self.max_input_length, other.max_input_length),
+            max_tokens: min(self.max_tokens, other.max_tokens),
+            max_depth: min(self.max_depth, other.max_depth),
+            max_steps: min(self.max_steps, other.max_steps),
+            max_call_depth: min(self.max_call_depth, other.max_call_depth),
+            #[cfg(feature = "std")]
+            timeout: match (self.timeout, other.timeout) {
+                (Some(lhs), Some(rhs)) => Some(lhs.min(rhs)),
+                (lhs, rhs) => lhs.or(rhs),
+            },
+        };
+    }
+
+    pub fn check_input(&self, expression: &str) -> Result<(), Limit> {
+        if let Some(max) = exceeds(expression.chars().count(), self.max_input_length) {
+            return Err(Limit::InputLength(max));
+        }
+
+        return Ok(());
+    }
+
+    /// Fails with the span of the first token that goes over a limit.
+    pub fn check_tokens(&self, tokens: &[Token]) -> Result<(), (Limit, Span)> {
+        if let Some(max) = exceeds(tokens.len(), self.max_tokens) {
+            return Err((Limit::Tokens(max), tokens[max].span()));
+        }
+
+        let Some(max) = self.max_depth else {
+            return Ok(());
+        };
+        let mut depth = 0usize;
+
+        for token in tokens {
+            match token.kind() {
+                TokenKind::OpenParen => depth += 1,
+                TokenKind::CloseParen => depth = depth.saturating_sub(1),
+                _ => continue,
+            }
+
+            if depth > max {
+                return Err((Limit::Depth(max), token.span()));
+            }
+        }
+
+        return Ok(());
+    }
+
+    /// For token streams checked as they grow, such as calls being expanded.
+    pub fn check_token_count(&self, count: usize) -> Result<(), Limit> {
+        if let Some(max) = exceeds(count, self.max_tokens) {
+            return Err(Limit::Tokens(max));
+        }
+
+        return Ok(());
+    }
+
+    pub fn check_call_depth(&self, depth: usize) -> Result<(), Limit> {
+        if let Some(max) = exceeds(depth, self.max_call_depth) {
+            return Err(Limit::CallDepth(max));
+        }
+
+        return Ok(());
+    }
+}
+
+/// When the `Limits::timeout` of a call runs out.
+pub struct Deadline {
+    #[cfg(feature = "std")]
+    end: Option<(Instant, Duration)>,
+}
+
+impl Deadline {
+    pub fn start(limits: &Limits) -> Self {
+        #[cfg(feature = "std")]
+        return Self {
+            end: limits
+                .timeout
+                .map(|timeout| (Instant::now() + timeout, timeout)),
+        };
+
+        #[cfg(not(feature = "std"))]
+        {
+            let _ = limits;
+            return Self {};
+        }
+    }
+
+    pub fn check(&self) -> Result<(), ParseError> {
+        #[cfg(feature = "std")]
+        if let Some((end, timeout)) = self.end {
+            if Instant::now() > end {
+                return Err(ParseError::LimitExceeded(Limit::Time(timeout)));
+            }
+        }
+
+        return Ok(());
+    }
+}
+
+/// How far one evaluation may go, checked by the evaluator before each step.
+#[derive(Clone, Copy, Default)]
+pub struct Budget<'d> {
+    pub max_steps: Option<usize>,
+    pub deadline: Option<&'d Deadline>,
+}
+
+impl<'d> Budget<'d> {
+    pub fn new(limits: &Limits, deadline: &'d Deadline) -> Self {
+        return Self {
+            max_steps: limits.max_steps,
+            deadline: Some(deadline),
+        };
+    }
+
+    /// Fails if running step `step`, counted from 0, goes over the budget.
+    #[inline]
+    pub fn check(&self, step: usize) -> Result<(), ParseError> {
+        if let Some(max) = self.max_steps.filter(|max| step >= *max) {
+            return Err(ParseError::LimitExceeded(Limit::Steps(max)));
+        }
+
+        if step % DEADLINE_INTERVAL == DEADLINE_INTERVAL - 1 {
+            if let Some(deadline) = self.deadline {
+                deadline.check()?;
+            }
+        }
+
+        return Ok(());
+    }
+}
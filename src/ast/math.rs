@@ -0,0 +1,102 @@
+//! Float functions that `core` does not provide. With `std` they forward to
+//! the inherent methods, otherwise to the `libm` crate.
+
+#[cfg(feature = "std")]
+mod imp {
+    pub fn sin(x: f32) -> f32 {
+        x.sin()
+    }
+
+    pub fn cos(x: f32) -> f32 {
+        x.cos()
+    }
+
+    pub fn tan(x: f32) -> f32 {
+        x.tan()
+    }
+
+    pub fn log10(x: f32) -> f32 {
+        x.log10()
+    }
+
+    pub fn powf(x: f32, y: f32) -> f32 {
+        x.powf(y)
+    }
+}
+
+#[cfg(not(feature = "std"))]
+mod imp {
+    pub fn sin(x: f32) -> f32 {
+        libm::sinf(x)
+    }
+
+    pub fn cos(x: f32) -> f32 {
+        libm::cosf(x)
+    }
+
+    pub fn tan(x: f32) -> f32 {
+        libm::tanf(x)
+    }
+
+    pub fn log10(x: f32) -> f32 {
+        libm::log10f(x)
+    }
+
+    pub fn powf(x: f32, y: f32) -> f32 {
+        libm::powf(x, y)
+    }
+}
+
+pub use imp::*;
+
+/// The same functions in double precision, for `Quantity`.
+#[cfg(feature = "units")]
+pub mod double {
+    #[cfg(feature = "std")]
+    mod imp {
+        pub fn sin(x: f64) -> f64 {
+            x.sin()
+        }
+
+        pub fn cos(x: f64) -> f64 {
+            x.cos()
+        }
+
+        pub fn tan(x: f64) -> f64 {
+            x.tan()
+        }
+
+        pub fn log10(x: f64) -> f64 {
+            x.log10()
+        }
+
+        pub fn powf(x: f64, y: f64) -> f64 {
+            x.powf(y)
+        }
+    }
+
+    #[cfg(not(feature = "std"))]
+    mod imp {
+        pub fn sin(x: f64) -> f64 {
+            libm::sin(x)
+        }
+
+        pub fn cos(x: f64) -> f64 {
+            libm::cos(x)
+        }
+
+        pub fn tan(x: f64) -> f64 {
+            libm::tan(x)
+        }
+
+        pub fn log10(x: f64) -> f64 {
+            libm::log10(x)
+        }
+
+        pub fn powf(x: f64, y: f64) -> f64 {
+            libm::pow(x, y)
+        }
+    }
+
+    pub use imp::*;
+}
@@ -1,3 +1,37 @@
+pub mod batch;
+#[cfg(feature = "bigint")]
+mod bigint;
+pub mod bytecode;
+pub mod cache;
+#[cfg(feature = "repl")]
+mod commands;
+pub mod compiled;
+#[cfg(feature = "complex")]
+mod complex;
+#[cfg(feature = "repl")]
+pub mod config;
+#[cfg(feature = "repl")]
+pub mod console;
+pub mod console_error;
+#[cfg(feature = "decimal")]
+mod decimal;
+#[cfg(feature = "repl")]
+mod editor;
+pub mod evaluator;
+pub mod expr;
+pub mod format;
 pub mod interpreter;
+#[cfg(feature = "jit")]
+pub mod jit;
 pub mod lexer;
+pub mod limits;
+mod math;
+pub mod number;
+mod optimizer;
 pub mod parser;
+pub mod registry;
+pub mod session;
+pub mod statement;
+pub mod trace;
+#[cfg(feature = "units")]
+pub mod units;
@@ -0,0 +1,187 @@
+#![allow(clippy::needless_return)]
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::fmt;
+
+use super::{
+    expr::{Expr, ExprKind},
+    interpreter::Interpreter,
+    lexer::{Span, TokenKind},
+    limits::{Budget, Deadline},
+    parser::{AngleUnit, ParseError},
+};
+
+/// A type expressions can be evaluated in instead of `f32`, through
+/// `Interpreter::interpret_as`. What the type can not compute exactly is an
+/// error rather than rounded, and the float policy does not apply.
+pub trait Number: Sized + fmt::Display {
+    /// Reads a literal as it is written in the input, such as `12` or `0.25`.
+    fn parse_literal(literal: &str) -> Option<Self>;
+
+    /// Converts a variable of the interpreter, which holds it as `f32`.
+    fn from_f32(value: f32) -> Option<Self>;
+
+    /// A name the type gives a meaning to, such as a unit.
+    fn named(name: &str) -> Option<Self> {
+        let _ = name;
+        return None;
+    }
+
+    /// `pi`, if the type has it.
+    fn pi() -> Option<Self>;
+
+    /// `self <operator> rhs` for `+`, `-`, `*`, `/` and `^`.
+    fn binary(&self, operator: &TokenKind, rhs: &Self) -> Result<Self, ParseError>;
+
+    /// `sin`, `cos`, `tan` or `log` of `self`.
+    fn apply(&self, function: &TokenKind) -> Result<Self, ParseError>;
+
+    /// How `self <operator> rhs` is written in errors, such as `2/0`.
+    fn written(&self, operator: &TokenKind, rhs: &Self) -> String {
+        return format!("{self}{operator}{rhs}");
+    }
+
+    /// How `function` of `self` is written in errors, such as `Sin 2`.
+    fn applied(&self, function: &TokenKind) -> String {
+        return format!("{function}{self}");
+    }
+}
+
+enum Visit<'t> {
+    Enter(&'t Expr),
+    Exit(&'t Expr),
+}
+
+impl Interpreter {
+    /// Evaluates `expression` in the number type `N` rather than `f32`.
+    /// Literals are read from the input at full precision, variables are
+    /// converted from the `f32` they are stored as, and names the
+    /// interpreter does not know are looked up with `Number::named`. User
+    /// functions are expanded as in `interpret`, within the same limits.
+    pub fn interpret_as<N: Number>(&mut self, expression: &str) -> Result<N, ParseError> {
+        let deadline = Deadline::start(self.limits());
+        let (tree, diagnostics) = self.diagnose(expression);
+
+        if let Some(diagnostic) = diagnostics.into_iter().next() {
+            return Err(diagnostic.into_error());
+        }
+        let Some(tree) = tree else {
+            return Err(ParseError::ExpressionEmpty);
+        };
+        deadline.check()?;
+
+        let budget = Budget::new(self.limits(), &deadline);
+        // spans count characters, literals are cut out of the input by byte
+        let offsets: Vec<usize> = expression
+            .char_indices()
+            .map(|(offset, _)| offset)
+            .chain([expression.len()])
+            .collect();
+        let literal = |span: Span| &expression[offsets[span.start]..offsets[span.end]];
+
+        return self.evaluate_tree(&tree, &literal, budget);
+    }
+
+    // walked with an explicit stack, a long chain is as deep as it is long
+    fn evaluate_tree<'s, N: Number>(
+        &self,
+        tree: &Expr,
+        literal: &dyn Fn(Span) -> &'s str,
+        budget: Budget,
+    ) -> Result<N, ParseError> {
+        let mut pending = vec![Visit::Enter(tree)];
+        let mut values: Vec<N> = Vec::new();
+        let mut step = 0;
+
+        while let Some(visit) = pending.pop() {
+            budget.check(step)?;
+            step += 1;
+
+            let expr = match visit {
+                Visit::Enter(expr) => {
+                    match expr.kind() {
+                        ExprKind::Call(_, arg) => {
+                            pending.extend([Visit::Exit(expr), Visit::Enter(arg)])
+                        }
+                        ExprKind::Binary(_, lhs, rhs) => pending.extend([
+                            Visit::Exit(expr),
+                            Visit::Enter(rhs),
+                            Visit::Enter(lhs),
+                        ]),
+                        _ => values.push(self.leaf(expr, literal(expr.span()))?),
+                    }
+                    continue;
+                }
+                Visit::Exit(expr) => expr,
+            };
+
+            let value = match expr.kind() {
+                ExprKind::Call(function, _) => {
+                    let arg = values.pop().ok_or(ParseError::MissingExpression)?;
+                    self.to_radians(function, arg)?.apply(function)?
+                }
+                ExprKind::Binary(operator, ..) => {
+                    let (Some(rhs), Some(lhs)) = (values.pop(), values.pop()) else {
+                        return Err(ParseError::MissingExpression);
+                    };
+                    lhs.binary(operator, &rhs)?
+                }
+                _ => return Err(ParseError::MissingExpression),
+            };
+            values.push(value);
+        }
+
+        return values.pop().ok_or(ParseError::ExpressionEmpty);
+    }
+
+    /// `literal` is the text `expr` spans.
+    fn leaf<N: Number>(&self, expr: &Expr, literal: &str) -> Result<N, ParseError> {
+        match expr.kind() {
+            ExprKind::Number(value) => {
+                // a literal from the body of a user function has the span
+                // of the call, so only its `f32` value is left
+                let parsed = match literal.parse::<f32>() == Ok(*value) {
+                    true => N::parse_literal(literal),
+                    false => N::from_f32(*value),
+                };
+
+                return parsed.ok_or_else(|| ParseError::InvalidNumber(literal.to_string()));
+            }
+            ExprKind::Pi => return N::pi().ok_or(ParseError::Unsupported("pi".to_string())),
+            ExprKind::Identifier(name) => {
+                if let Some(value) = self.registry().variable(name) {
+                    return N::from_f32(value)
+                        .ok_or_else(|| ParseError::Unsupported(format!("{name} = {value:?}")));
+                }
+                if let Some(value) = N::named(name) {
+                    return Ok(value);
+                }
+
+                let suggestions = self.registry().suggest(name);
+                return Err(ParseError::UnknownIdentifier(name.to_string(), suggestions));
+            }
+            _ => return Err(ParseError::MissingExpression),
+        }
+    }
+
+    /// Converts the argument of `sin`, `cos` and `tan` from degrees.
+    fn to_radians<N: Number>(&self, function: &TokenKind, arg: N) -> Result<N, ParseError> {
+        let trigonometric = matches!(function, TokenKind::Sin | TokenKind::Cos | TokenKind::Tan);
+        if !trigonometric || self.angle_unit() == AngleUnit::Radians {
+            return Ok(arg);
+        }
+
+        let pi = N::pi().ok_or(ParseError::Unsupported("pi".to_string()))?;
+        let half_turn =
+            N::parse_literal("180").ok_or(ParseError::Unsupported("180".to_string()))?;
+
+        return arg
+            .binary(&TokenKind::Asterix, &pi)?
+            .binary(&TokenKind::Slash, &half_turn);
+    }
+}
@@ -0,0 +1,269 @@
+#![allow(clippy::needless_return)]
+
+use alloc::{collections::BTreeMap, vec, vec::Vec};
+
+use super::{
+    bytecode::{Instruction, Program},
+    evaluator::Evaluation,
+    math,
+    parser::FloatPolicy,
+};
+
+/// A value computed by a program. Operands refer to earlier nodes, so equal
+/// sub-expressions end up as the same node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Node {
+    /// Stored as bits so nodes can be compared and ordered.
+    Const(u32),
+    Load(u32),
+    Unary(Operator, usize),
+    Binary(Operator, usize, usize),
+}
+
+/// `Instruction` without its operands, so it can be part of a `Node`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Operator(usize);
+
+const OPERATORS: &[Instruction] = &[
+    Instruction::Add,
+    Instruction::Sub,
+    Instruction::Mul,
+    Instruction::Div,
+    Instruction::Pow,
+    Instruction::Sin,
+    Instruction::Cos,
+    Instruction::Tan,
+    Instruction::Log,
+];
+
+impl Operator {
+    fn of(instruction: &Instruction) -> Option<Operator> {
+        return OPERATORS
+            .iter()
+            .position(|operator| operator == instruction)
+            .map(Operator);
+    }
+
+    fn instruction(self) -> Instruction {
+        return OPERATORS[self.0];
+    }
+}
+
+/// Builds the expression graph, folding and simplifying as it goes.
+struct Graph {
+    nodes: Vec<Node>,
+    ids: BTreeMap<Node, usize>,
+    /// Whether equal operations become one node.
+    share: bool,
+}
+
+impl Graph {
+    fn add(&mut self, node: Node) -> usize {
+        let operation = matches!(node, Node::Unary(..) | Node::Binary(..));
+
+        if let Some(id) = self.ids.get(&node).filter(|_| self.share || !operation) {
+            return *id;
+        }
+
+        self.nodes.push(node);
+        self.ids.insert(node, self.nodes.len() - 1);
+
+        return self.nodes.len() - 1;
+    }
+
+    fn constant(&self, id: usize) -> Option<f32> {
+        match self.nodes[id] {
+            Node::Const(bits) => return Some(f32::from_bits(bits)),
+            _ => return None,
+        }
+    }
+
+    fn unary(&mut self, operator: Operator, arg: usize) -> usize {
+        let instruction = operator.instruction();
+
+        if let Some(arg) = self.constant(arg) {
+            let value = match instruction {
+                Instruction::Sin => math::sin(arg),
+                Instruction::Cos => math::cos(arg),
+                Instruction::Tan => math::tan(arg),
+                _ => math::log10(arg),
+            };
+
+            // anything the float policy would see is left for the evaluator
+            if Evaluation::in_domain(&instruction.kind(), arg) && value.is_finite() {
+                return self.add(Node::Const(value.to_bits()));
+            }
+        }
+
+        return self.add(Node::Unary(operator, arg));
+    }
+
+    fn binary(&mut self, operator: Operator, left: usize, right: usize) -> usize {
+        let instruction = operator.instruction();
+
+        if let (Some(x), Some(y)) = (self.constant(left), self.constant(right)) {
+            let value = match instruction {
+                Instruction::Add => x + y,
+                Instruction::Sub => x - y,
+                Instruction::Mul => x * y,
+                Instruction::Div => x / y,
+                _ => math::powf(x, y),
+            };
+
+            if value.is_finite() {
+                return self.add(Node::Const(value.to_bits()));
+            }
+        }
+
+        let is = |id: usize, value: f32| self.constant(id) == Some(value);
+
+        let identity = match instruction {
+            Instruction::Add if is(right, 0.0) => Some(left),
+            Instruction::Add if is(left, 0.0) => Some(right),
+            Instruction::Sub if is(right, 0.0) => Some(left),
+            Instruction::Mul if is(right, 1.0) => Some(left),
+            Instruction::Mul if is(left, 1.0) => Some(right),
+            Instruction::Div | Instruction::Pow if is(right, 1.0) => Some(left),
+            _ => None,
+        };
+
+        if let Some(id) = identity {
+            return id;
+        }
+
+        return self.add(Node::Binary(operator, left, right));
+    }
+}
+
+impl Program {
+    /// Folds constant sub-expressions, drops operations that leave their
+    /// operand unchanged (`x * 1`, `x + 0`, `x - 0`, `x / 1`, `x ^ 1`) and
+    /// computes repeated sub-expressions only once.
+    ///
+    /// Errors and warnings are kept: anything the float policy would have to
+    /// look at is left to run, and under `FloatPolicy::Warn` repeated
+    /// sub-expressions are not shared so each can warn. Results are the same
+    /// except for the sign of a zero where `x + 0` or `x - -0` was dropped.
+    pub fn optimize(&self, float_policy: FloatPolicy) -> Program {
+        let mut graph = Graph {
+            nodes: Vec::new(),
+            ids: BTreeMap::new(),
+            share: float_policy != FloatPolicy::Warn,
+        };
+        let mut stack: Vec<usize> = Vec::new();
+
+        for instruction in self.instructions() {
+            let id = match *instruction {
+                Instruction::Const(value) => graph.add(Node::Const(value.to_bits())),
+                Instruction::Load(slot) => graph.add(Node::Load(slot)),
+                // already optimized
+                Instruction::Save(_) | Instruction::Recall(_) => return self.clone(),
+                operator => {
+                    let Some(operator) = Operator::of(&operator) else {
+                        return self.clone();
+                    };
+
+                    if instruction.arity() == 2 {
+                        let (Some(right), Some(left)) = (stack.pop(), stack.pop()) else {
+                            return self.clone();
+                        };
+                        graph.binary(operator, left, right)
+                    } else {
+                        let Some(arg) = stack.pop() else {
+                            return self.clone();
+                        };
+                        graph.unary(operator, arg)
+                    }
+                }
+            };
+
+            stack.push(id);
+        }
+
+        // values left below the result are never used, dropping them could
+        // hide their errors
+        let [root] = stack[..] else {
+            return self.clone();
+        };
+
+        let uses = count_uses(&graph.nodes, root);
+        let instructions = emit(&graph.nodes, &uses, root);
+
+        return Program::new(instructions).unwrap_or_else(|_| self.clone());
+    }
+}
+
+/// How often each node is reached from `root`. Walks with an explicit stack,
+/// since a long chain like `x + 1 + ... + 1` is as deep as it is long.
+fn count_uses(nodes: &[Node], root: usize) -> Vec<usize> {
+    let mut uses = vec![0usize; nodes.len()];
+    let mut pending = vec![root];
+
+    while let Some(id) = pending.pop() {
+        uses[id] += 1;
+
+        // operands are only counted the first time a node is reached, since
+        // every later use is a recall of the saved value
+        if uses[id] > 1 {
+            continue;
+        }
+
+        match nodes[id] {
+            Node::Unary(_, arg) => pending.push(arg),
+            Node::Binary(_, left, right) => pending.extend([right, left]),
+            _ => {}
+        }
+    }
+
+    return uses;
+}
+
+/// Emits `root` in post-order, saving every operation used more than once
+/// and recalling it after that.
+fn emit(nodes: &[Node], uses: &[usize], root: usize) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut temporaries: Vec<Option<u32>> = vec![None; nodes.len()];
+    let mut next_temporary = 0;
+    // each node is pushed once to emit its operands, then again to emit itself
+    let mut pending = vec![(root, false)];
+
+    while let Some((id, operands_emitted)) = pending.pop() {
+        if let Some(temporary) = temporaries[id] {
+            instructions.push(Instruction::Recall(temporary));
+            continue;
+        }
+
+        let operator = match nodes[id] {
+            Node::Const(bits) => {
+                instructions.push(Instruction::Const(f32::from_bits(bits)));
+                continue;
+            }
+            Node::Load(slot) => {
+                instructions.push(Instruction::Load(slot));
+                continue;
+            }
+            Node::Unary(operator, _) | Node::Binary(operator, ..) => operator,
+        };
+
+        if !operands_emitted {
+            pending.push((id, true));
+
+            match nodes[id] {
+                Node::Unary(_, arg) => pending.push((arg, false)),
+                Node::Binary(_, left, right) => pending.extend([(right, false), (left, false)]),
+                _ => {}
+            }
+            continue;
+        }
+
+        instructions.push(operator.instruction());
+
+        if uses[id] > 1 {
+            instructions.push(Instruction::Save(next_temporary));
+            temporaries[id] = Some(next_temporary);
+            next_temporary += 1;
+        }
+    }
+
+    return instructions;
+}
@@ -1,7 +1,23 @@
 #![allow(clippy::needless_return)]
-use std::{collections::VecDeque, f32::consts::PI};
-
-use super::lexer::{Token, TokenKind};
+use alloc::{
+    boxed::Box,
+    collections::VecDeque,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::{f32::consts::PI, fmt};
+
+use super::{
+    compiled::CompiledExpr,
+    evaluator::Evaluation,
+    expr::{Expr, ExprKind},
+    lexer::{Span, Token, TokenKind},
+    limits::{Budget, Limit},
+    registry::Registry,
+    trace::{Level, Stage, Tracer},
+};
 
 #[derive(Debug)]
 pub enum ParseError {
@@ -10,6 +26,29 @@ pub enum ParseError {
     ExpressionEmpty,
     DivisionByZero,
     UnexpectedOperator(TokenKind),
+    NonFinite(String),
+    OutOfDomain(String),
+    InvalidNumber(String),
+    InvalidSymbol(char),
+    UnexpectedToken(TokenKind),
+    UnknownIdentifier(String, Vec<String>),
+    InvalidIdentifier(String),
+    UnboundVariable(String),
+    /// A column passed to `CompiledExpr::eval_batch`, with its length and
+    /// the number of rows expected.
+    ColumnLength(String, usize, usize),
+    LimitExceeded(Limit),
+    /// A user function called with the wrong number of arguments, with the
+    /// number it takes and the number given.
+    ArgumentCount(String, usize, usize),
+    RecursiveFunction(String),
+    /// Input made only of function definitions where a value was expected.
+    NoValue,
+    /// An operation the number type of `Interpreter::interpret_as` has no
+    /// exact result for, such as `sin` of a `BigInt`.
+    Unsupported(String),
+    /// Quantities combined or passed to a function in units that do not fit.
+    IncompatibleUnits(String),
 }
 
 impl ParseError {
@@ -20,269 +59,682 @@ impl ParseError {
             ParseError::ExpressionEmpty => String::from("Expression can not be empty"),
             ParseError::DivisionByZero => String::from("Can not divide by 0"),
             Self::UnexpectedOperator(op) => format!("Unexpected operator: `{:?}`", op),
+            Self::NonFinite(operation) => format!("`{operation}` has no finite result"),
+            Self::OutOfDomain(operation) => {
+                format!("`{operation}` is outside the domain of the function")
+            }
+            Self::InvalidNumber(number) => format!("`{number}` is not a valid number"),
+            Self::InvalidSymbol(symbol) => format!("Unknown symbol: `{symbol}`"),
+            Self::UnexpectedToken(token) => format!("Unexpected token: `{:?}`", token),
+            Self::UnknownIdentifier(ident, suggestions) => match suggestions.as_slice() {
+                [] => format!("Unknown identifier: `{ident}`"),
+                [suggestion] => {
+                    format!("Unknown identifier: `{ident}`, did you mean `{suggestion}`?")
+                }
+                suggestions => format!(
+                    "Unknown identifier: `{ident}`, did you mean one of `{}`?",
+                    suggestions.join("`, `")
+                ),
+            },
+            Self::InvalidIdentifier(ident) => format!("`{ident}` can not be used as a name"),
+            Self::UnboundVariable(ident) => format!("No value given for variable `{ident}`"),
+            Self::ColumnLength(ident, found, expected) => {
+                format!("Column for `{ident}` has {found} rows, expected {expected}")
+            }
+            Self::LimitExceeded(limit) => format!("Expression is {limit}"),
+            Self::ArgumentCount(function, expected, found) => {
+                format!("`{function}` takes {expected} arguments but was given {found}")
+            }
+            Self::RecursiveFunction(function) => {
+                format!("`{function}` calls itself, which is not supported")
+            }
+            Self::NoValue => String::from("Input only defines functions and has no value"),
+            Self::Unsupported(operation) => {
+                format!("`{operation}` has no exact result in this number type")
+            }
+            Self::IncompatibleUnits(operation) => format!("`{operation}` has incompatible units"),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+impl core::error::Error for ParseError {}
+
+/// A `ParseError` together with the part of the input it refers to.
+#[derive(Debug)]
+pub struct Diagnostic {
+    error: ParseError,
+    span: Span,
+}
+
+impl Diagnostic {
+    pub fn new(error: ParseError, span: Span) -> Self {
+        return Self { error, span };
+    }
+
+    pub fn error(&self) -> &ParseError {
+        return &self.error;
+    }
+
+    pub fn span(&self) -> Span {
+        return self.span;
+    }
+
+    pub fn into_error(self) -> ParseError {
+        return self.error;
+    }
+}
+
+/// Decides what `execute` does when an operation leaves the finite numbers,
+/// either because it overflowed or because a built-in function was applied
+/// outside its domain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FloatPolicy {
+    /// Fail with an error naming the operation.
+    #[default]
+    Strict,
+    /// Pass NaN and infinities through as IEEE 754 defines them.
+    Ieee,
+    /// Pass the value through and record the error as a warning.
+    Warn,
+}
+
+impl FloatPolicy {
+    pub fn from_name(name: &str) -> Option<FloatPolicy> {
+        let policy = match name.to_lowercase().as_str() {
+            "strict" => FloatPolicy::Strict,
+            "ieee" => FloatPolicy::Ieee,
+            "warn" => FloatPolicy::Warn,
+            _ => return None,
+        };
+
+        return Some(policy);
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FloatPolicy::Strict => return "strict",
+            FloatPolicy::Ieee => return "ieee",
+            FloatPolicy::Warn => return "warn",
         }
     }
 }
 
-pub struct Parser;
+/// Unit `sin`, `cos` and `tan` take their argument in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AngleUnit {
+    #[default]
+    Radians,
+    /// Arguments are converted to radians by multiplying with `pi / 180`
+    /// before the function is applied, so errors show the converted value.
+    Degrees,
+}
+
+impl AngleUnit {
+    pub fn from_name(name: &str) -> Option<AngleUnit> {
+        let unit = match name.to_lowercase().as_str() {
+            "rad" | "radians" => AngleUnit::Radians,
+            "deg" | "degrees" => AngleUnit::Degrees,
+            _ => return None,
+        };
+
+        return Some(unit);
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AngleUnit::Radians => return "rad",
+            AngleUnit::Degrees => return "deg",
+        }
+    }
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+pub struct Parser {
+    float_policy: FloatPolicy,
+    angle_unit: AngleUnit,
+    warnings: Vec<ParseError>,
+    registry: Registry,
+    tracer: Tracer,
+}
 
 impl Parser {
     pub fn new() -> Self {
-        return Self;
+        return Self {
+            float_policy: FloatPolicy::default(),
+            angle_unit: AngleUnit::default(),
+            warnings: Vec::new(),
+            registry: Registry::new(),
+            tracer: Tracer::default(),
+        };
     }
 
-    fn balance_operators(&mut self, tokens: Vec<Token>) -> Result<Vec<Token>, ParseError> {
-        let mut out = vec![];
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = tracer;
+    }
 
-        // - + => error
-        // + - => -
-        // - - => +
+    pub fn registry(&self) -> &Registry {
+        return &self.registry;
+    }
 
-        let mut skip_next = false;
-        for (index, token) in tokens.clone().into_iter().enumerate() {
-            match token.kind() {
-                TokenKind::Minus => {
-                    let next = tokens
-                        .get(index + 1)
-                        .ok_or(ParseError::MissingExpression)?
-                        .kind();
+    pub fn registry_mut(&mut self) -> &mut Registry {
+        return &mut self.registry;
+    }
 
-                    if matches!(next, TokenKind::Minus) {
-                        skip_next = true;
-                        out.push(Token::new(TokenKind::Plus));
-                    }
-                    if matches!(next, TokenKind::Plus) {
-                        return Err(ParseError::UnexpectedOperator(TokenKind::Plus));
-                    }
+    pub fn float_policy(&self) -> FloatPolicy {
+        return self.float_policy;
+    }
 
-                    if !skip_next {
-                        skip_next = false;
-                        out.push(token)
-                    }
-                }
-                TokenKind::Plus => {
-                    let next = tokens
-                        .get(index + 1)
-                        .ok_or(ParseError::MissingExpression)?
-                        .kind();
-                    if matches!(next, TokenKind::Minus) {
-                        skip_next = true;
-                        out.push(Token::new(TokenKind::Minus));
-                    }
-                    if matches!(next, TokenKind::Plus) {
-                        return Err(ParseError::UnexpectedOperator(TokenKind::Plus));
-                    }
+    pub fn set_float_policy(&mut self, policy: FloatPolicy) {
+        self.float_policy = policy;
+    }
 
-                    if !skip_next {
-                        skip_next = false;
-                        out.push(token)
-                    }
-                }
-                _ => out.push(token),
-            }
-        }
-        println!("{out:?}");
-        return Ok(out);
+    pub fn angle_unit(&self) -> AngleUnit {
+        return self.angle_unit;
     }
 
-    fn insert_parenthesis(&mut self, tokens: Vec<Token>) -> Result<Vec<Token>, ParseError> {
-        let mut out = Vec::new();
-        let mut skip_next = false;
-        for (index, token) in tokens.clone().into_iter().enumerate() {
-            match token.kind() {
-                TokenKind::Cos | TokenKind::Sin | TokenKind::Tan | TokenKind::Log => {
-                    out.push(Token::new(TokenKind::OpenParen));
-                    out.push(token);
+    /// Applies to expressions parsed from now on.
+    pub fn set_angle_unit(&mut self, unit: AngleUnit) {
+        self.angle_unit = unit;
+    }
 
-                    let next = tokens.get(index + 1).ok_or(ParseError::MissingExpression)?;
+    /// Warnings recorded by the last call to `execute` under `FloatPolicy::Warn`.
+    pub fn warnings(&self) -> &[ParseError] {
+        return &self.warnings;
+    }
 
-                    out.push(next.to_owned());
-                    out.push(Token::new(TokenKind::CloseParen));
+    //reverse polish notation
+    pub fn execute(&mut self, tokens: &VecDeque<Token>) -> Result<f32, ParseError> {
+        return self.execute_in_order(tokens.iter().rev());
+    }
 
-                    skip_next = true;
-                }
-                TokenKind::Power => {
-                    if index == 0 {
-                        return Err(ParseError::MissingExpression);
-                    }
+    /// Runs the bytecode of `compiled`, taking variables from the registry.
+    pub fn execute_compiled(&mut self, compiled: &CompiledExpr) -> Result<f32, ParseError> {
+        return self.execute_within(compiled, Budget::default());
+    }
 
-                    out.remove(index - 1);
+    /// `execute_compiled`, stopping once `budget` runs out.
+    pub(crate) fn execute_within(
+        &mut self,
+        compiled: &CompiledExpr,
+        budget: Budget,
+    ) -> Result<f32, ParseError> {
+        self.warnings.clear();
+
+        let bindings = compiled
+            .variables()
+            .iter()
+            .map(|name| self.registry.resolve(name))
+            .collect::<Result<Vec<f32>, ParseError>>()?;
+
+        let mut evaluation = Evaluation {
+            float_policy: self.float_policy,
+            warnings: &mut self.warnings,
+            tracer: &self.tracer,
+            budget,
+        };
+
+        return compiled.program().run(&bindings, &mut evaluation);
+    }
 
-                    out.push(Token::new(TokenKind::OpenParen));
+    /// Like `execute`, but takes the tokens already in evaluation order.
+    pub fn execute_in_order<'t, I>(&mut self, tokens: I) -> Result<f32, ParseError>
+    where
+        I: Iterator<Item = &'t Token>,
+    {
+        self.warnings.clear();
+
+        let registry = &self.registry;
+        let mut evaluation = Evaluation {
+            float_policy: self.float_policy,
+            warnings: &mut self.warnings,
+            tracer: &self.tracer,
+            budget: Budget::default(),
+        };
+
+        return evaluation.run(tokens, |ident| registry.resolve(ident));
+    }
 
-                    out.push(tokens[index - 1].clone());
-                    out.push(token);
+    /// Parses `stream` into reverse polish notation, stored back to front,
+    /// from the same tree `parse_recovering` builds. Fails with the first
+    /// error in the input.
+    pub fn parse(&mut self, stream: Vec<Token>) -> Result<VecDeque<Token>, ParseError> {
+        self.tracer.emit(Level::Debug, Stage::Parser, || {
+            stream
+                .iter()
+                .map(|token| token.kind().to_string())
+                .collect()
+        });
 
-                    let next = tokens.get(index + 1).ok_or(ParseError::MissingExpression)?;
+        let (tree, diagnostics) = self.build_tree(stream);
 
-                    out.push(next.to_owned());
-                    out.push(Token::new(TokenKind::CloseParen));
+        if let Some(diagnostic) = diagnostics.into_iter().next() {
+            return Err(diagnostic.into_error());
+        }
+        let tree = tree.ok_or(ParseError::ExpressionEmpty)?;
 
-                    skip_next = true;
-                }
-                // - + => -
-                // + - => -
-                // - - => +
-                _ => {
-                    if skip_next {
-                        skip_next = false;
-                        continue;
-                    }
+        let mut output_queue: VecDeque<Token> = postfix(&tree)?.into_iter().rev().collect();
 
-                    out.push(token.clone());
-                }
-            }
+        if self.angle_unit == AngleUnit::Degrees {
+            output_queue = Self::to_radians(output_queue);
         }
-        //println!("{out:?}");
-        return Ok(out);
+
+        self.tracer.emit(Level::Trace, Stage::Parser, || {
+            let rpn: Vec<String> = output_queue
+                .iter()
+                .rev()
+                .map(|token| format!("{:?}", token.kind()))
+                .collect();
+
+            format!("rpn: {}", rpn.join(" "))
+        });
+
+        return Ok(output_queue);
     }
 
-    //reverse polish notation
-    pub fn execute(&self, mut tokens: VecDeque<Token>) -> Result<f32, ParseError> {
-        let mut number_stack = vec![];
+    /// Multiplies the argument of every trigonometric function in `queue`
+    /// with `pi / 180`.
+    fn to_radians(queue: VecDeque<Token>) -> VecDeque<Token> {
+        let mut converted = VecDeque::with_capacity(queue.len());
 
-        while let Some(token) = tokens.pop_back() {
-            if let TokenKind::Pi = token.kind().clone() {
-                number_stack.push(PI);
-                continue;
+        // the queue is in reverse, so the function comes before its argument
+        for token in queue {
+            let trigonometric = matches!(
+                token.kind(),
+                TokenKind::Sin | TokenKind::Cos | TokenKind::Tan
+            );
+            let span = token.span();
+
+            converted.push_back(token);
+
+            if trigonometric {
+                converted.push_back(Token::spanned(TokenKind::Asterix, span));
+                converted.push_back(Token::spanned(TokenKind::Number(PI / 180.0), span));
             }
-            if token.kind().is_number() {
-                if let TokenKind::Number(value) = token.kind().clone() {
-                    number_stack.push(value)
+        }
+
+        return converted;
+    }
+
+    /// Parses the whole stream into a tree, recovering after each error so
+    /// that every problem is reported. Parts of the input that could not be
+    /// parsed show up as `ExprKind::Error` nodes.
+    pub fn parse_recovering(&mut self, stream: Vec<Token>) -> (Option<Expr>, Vec<Diagnostic>) {
+        let (tree, mut diagnostics) = self.build_tree(stream);
+        diagnostics.sort_by_key(|diagnostic| diagnostic.span().start);
+
+        return (tree, diagnostics);
+    }
+
+    /// `parse_recovering` with the diagnostics in the order they were found.
+    fn build_tree(&self, stream: Vec<Token>) -> (Option<Expr>, Vec<Diagnostic>) {
+        let mut diagnostics = Vec::new();
+        let mut depth = 0usize;
+
+        // Sync point: a `)` without an opening partner is reported and dropped
+        // up front so it cannot cut the rest of the expression off.
+        let stream: Vec<Token> = stream
+            .into_iter()
+            .filter(|token| match token.kind() {
+                TokenKind::OpenParen => {
+                    depth += 1;
+                    true
                 }
-            } else {
-                let mut is_div = false;
-                let operation: Box<dyn Fn(f32, f32) -> f32> = match token.kind() {
-                    super::lexer::TokenKind::Identifier(ident) => {
-                        continue;
-                    }
-                    super::lexer::TokenKind::Plus => Box::new(|x, y| x + y),
-                    super::lexer::TokenKind::Asterix => Box::new(|x, y| x * y),
-                    super::lexer::TokenKind::Minus => Box::new(|x, y| y - x),
-                    super::lexer::TokenKind::Power => Box::new(|x, y| y.powf(x)),
-                    super::lexer::TokenKind::Slash => {
-                        is_div = true;
-                        Box::new(|x, y| y / x)
-                    }
-                    super::lexer::TokenKind::Sin => {
-                        let rhs = number_stack.pop().unwrap();
+                TokenKind::CloseParen if depth == 0 => {
+                    diagnostics.push(Diagnostic::new(ParseError::ParensMismatch, token.span()));
+                    false
+                }
+                TokenKind::CloseParen => {
+                    depth -= 1;
+                    true
+                }
+                _ => true,
+            })
+            .collect();
+
+        if stream.is_empty() {
+            diagnostics.push(Diagnostic::new(
+                ParseError::ExpressionEmpty,
+                Span::default(),
+            ));
+            return (None, diagnostics);
+        }
 
-                        number_stack.push(rhs.sin());
-                        continue;
-                    }
+        let recovery = Recovery {
+            tokens: &stream,
+            position: 0,
+            diagnostics,
+            registry: &self.registry,
+            operands: Vec::new(),
+            pending: Vec::new(),
+        };
+        let (tree, diagnostics) = recovery.build();
+
+        return (Some(tree), diagnostics);
+    }
+}
 
-                    super::lexer::TokenKind::Cos => {
-                        let rhs = number_stack.pop().unwrap();
+/// The tokens of `tree` in evaluation order, without recursing so that
+/// deep trees cannot overflow the stack.
+fn postfix(tree: &Expr) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut pending = vec![(tree, false)];
 
-                        number_stack.push(rhs.cos());
-                        continue;
-                    }
+    while let Some((expr, operands_done)) = pending.pop() {
+        let span = expr.span();
+
+        let kind = match expr.kind() {
+            ExprKind::Call(_, arg) if !operands_done => {
+                pending.extend([(expr, true), (&**arg, false)]);
+                continue;
+            }
+            ExprKind::Binary(_, lhs, rhs) if !operands_done => {
+                pending.extend([(expr, true), (&**rhs, false), (&**lhs, false)]);
+                continue;
+            }
+            ExprKind::Call(kind, _) | ExprKind::Binary(kind, ..) => kind.clone(),
+            ExprKind::Number(value) => TokenKind::Number(*value),
+            ExprKind::Pi => TokenKind::Pi,
+            ExprKind::Identifier(ident) => TokenKind::Identifier(ident.clone()),
+            ExprKind::Error => return Err(ParseError::MissingExpression),
+        };
+
+        tokens.push(Token::spanned(kind, span));
+    }
 
-                    super::lexer::TokenKind::Tan => {
-                        let rhs = number_stack.pop().unwrap();
+    return Ok(tokens);
+}
 
-                        number_stack.push(rhs.tan());
-                        continue;
-                    }
+/// An operator `Recovery` has read but not applied yet.
+enum Pending {
+    /// `+`, `-`, `*`, `/` or `^` waiting for its right operand.
+    Binary(TokenKind),
+    /// A built-in function waiting for its argument.
+    Call(TokenKind, Span),
+    /// An open parenthesis.
+    Group(Span),
+    /// Input following an unexpected token, parsed to report its errors and
+    /// then dropped.
+    Skipped,
+}
 
-                    super::lexer::TokenKind::Log => {
-                        let rhs = number_stack.pop().unwrap();
+/// Operator precedence parsing with explicit stacks, used by both
+/// `Parser::parse` and `Parser::parse_recovering`, so nesting is only
+/// bounded by `Limits`. Never bails out: a missing operand becomes an
+/// `ExprKind::Error` node and unexpected tokens are reported and skipped.
+///
+/// `+` and `-` bind loosest, then `*` and `/`, then `^`, which groups to the
+/// right. A built-in function applies to the operand right after it, so
+/// `sin 2 ^ 2` is `(sin 2) ^ 2`.
+struct Recovery<'t> {
+    tokens: &'t [Token],
+    position: usize,
+    diagnostics: Vec<Diagnostic>,
+    registry: &'t Registry,
+    operands: Vec<Expr>,
+    pending: Vec<Pending>,
+}
 
-                        number_stack.push(rhs.log10());
-                        continue;
-                    }
-                    unknown => unreachable!("Unhandled token: `{:?}`", unknown),
-                };
+impl<'t> Recovery<'t> {
+    fn peek(&self) -> Option<&'t Token> {
+        return self.tokens.get(self.position);
+    }
 
-                let lhs = number_stack.pop();
+    /// Empty span right behind the last token, used when something is missing.
+    fn end_span(&self) -> Span {
+        let end = self.tokens.last().map_or(0, |token| token.span().end);
 
-                let rhs = number_stack.pop();
+        return Span::new(end, end);
+    }
 
-                if lhs.is_none() || rhs.is_none() {
-                    return Err(ParseError::MissingExpression);
-                }
+    fn report(&mut self, error: ParseError, span: Span) {
+        self.diagnostics.push(Diagnostic::new(error, span));
+    }
 
-                if is_div && lhs.unwrap() == 0.0 {
-                    return Err(ParseError::DivisionByZero);
-                }
+    fn missing(&mut self, span: Span) {
+        self.report(ParseError::MissingExpression, span);
+        self.operands.push(Expr::error(span));
+    }
 
-                number_stack.push(operation(lhs.unwrap(), rhs.unwrap()))
-            }
+    fn build(mut self) -> (Expr, Vec<Diagnostic>) {
+        let mut expect_operand = true;
+
+        while let Some(token) = self.peek() {
+            expect_operand = match expect_operand {
+                true => self.operand(token),
+                false => self.operator(token),
+            };
         }
 
-        return Ok(number_stack.pop().unwrap_or(0.0));
-    }
+        if expect_operand {
+            let span = self.end_span();
+            self.missing(span);
+        }
 
-    //shunting yard
-    pub fn parse(&mut self, stream: Vec<Token>) -> Result<VecDeque<Token>, ParseError> {
-        let with_parens = self.insert_parenthesis(stream)?;
-        let with_ops_balanced = self.balance_operators(with_parens)?;
+        loop {
+            self.reduce(0, false);
 
-        for t in with_ops_balanced.clone() {
-            print!("{}", t.kind());
+            match self.pending.pop() {
+                Some(Pending::Group(open)) => {
+                    self.report(ParseError::ParensMismatch, open);
+                    self.close_group(open, None);
+                }
+                Some(_) => {
+                    self.operands.pop();
+                }
+                None => break,
+            }
         }
-        println!();
 
-        let token_stream = with_ops_balanced.into_iter().peekable();
-        let mut output_queue = VecDeque::new();
-        let mut operator_stack = vec![];
+        let tree = self
+            .operands
+            .pop()
+            .unwrap_or_else(|| Expr::error(Span::default()));
 
-        for token in token_stream {
-            if token.kind().is_number() {
-                output_queue.push_front(token.clone())
+        return (tree, self.diagnostics);
+    }
+
+    /// Reads `token` where an operand should be, returning whether one is
+    /// still expected.
+    fn operand(&mut self, token: &Token) -> bool {
+        let span = token.span();
+
+        let kind = match token.kind() {
+            TokenKind::Number(value) => ExprKind::Number(*value),
+            TokenKind::Pi => ExprKind::Pi,
+            TokenKind::Identifier(ident) => ExprKind::Identifier(ident.clone()),
+            TokenKind::Sin | TokenKind::Cos | TokenKind::Tan | TokenKind::Log => {
+                self.pending.push(Pending::Call(token.kind().clone(), span));
+                self.position += 1;
+                return true;
+            }
+            TokenKind::OpenParen => {
+                self.pending.push(Pending::Group(span));
+                self.position += 1;
+                return true;
             }
+            TokenKind::Equals | TokenKind::Comma | TokenKind::Semicolon | TokenKind::Newline => {
+                self.report(ParseError::UnexpectedToken(token.kind().clone()), span);
+                self.position += 1;
+                return true;
+            }
+            // an operator or `)` where an operand should be, it is read again
+            // as what follows the missing operand
+            _ => {
+                self.missing(Span::new(span.start, span.start));
+                return false;
+            }
+        };
 
-            if token.kind().is_operator() {
-                if !operator_stack.is_empty() {
-                    let mut top: &Token = &operator_stack[operator_stack.len() - 1];
+        self.operands.push(Expr::new(kind, span));
+        self.position += 1;
 
-                    let token_precedence = token.kind().precedence();
-                    let top_token_precedence = top.kind().precedence();
+        return false;
+    }
+
+    /// Reads `token` after a complete operand, returning whether an operand
+    /// is expected next.
+    fn operator(&mut self, token: &Token) -> bool {
+        match token.kind() {
+            TokenKind::Plus | TokenKind::Minus => {
+                let operator = self.additive_operator();
+                self.reduce(1, false);
+                self.pending.push(Pending::Binary(operator));
+            }
+            TokenKind::Asterix | TokenKind::Slash => {
+                self.position += 1;
+                self.reduce(2, false);
+                self.pending.push(Pending::Binary(token.kind().clone()));
+            }
+            TokenKind::Power => {
+                self.position += 1;
+                self.reduce(3, true);
+                self.pending.push(Pending::Binary(TokenKind::Power));
+            }
+            TokenKind::CloseParen => {
+                self.position += 1;
 
-                    while !top.kind().is_open_paren() && top_token_precedence >= token_precedence {
-                        output_queue.push_front(operator_stack.pop().unwrap());
+                // groups in the input are balanced by `parse_recovering`
+                loop {
+                    self.reduce(0, false);
 
-                        if let Some(tok) = &operator_stack.last() {
-                            top = tok
-                        } else {
+                    match self.pending.pop() {
+                        Some(Pending::Group(open)) => {
+                            self.close_group(open, Some(token.span()));
+                            break;
+                        }
+                        Some(_) => {
+                            self.operands.pop();
+                        }
+                        None => {
+                            self.report(ParseError::ParensMismatch, token.span());
                             break;
                         }
                     }
                 }
 
-                operator_stack.push(token.clone())
+                return false;
             }
+            TokenKind::Equals | TokenKind::Comma | TokenKind::Semicolon | TokenKind::Newline => {
+                self.report(
+                    ParseError::UnexpectedToken(token.kind().clone()),
+                    token.span(),
+                );
+                self.position += 1;
+
+                return false;
+            }
+            // Sync point: an operand right after another is reported, and the
+            // expression it starts is parsed for its errors and then dropped.
+            _ => {
+                self.unexpected_operand(token);
+                self.reduce(0, false);
+                self.pending.push(Pending::Skipped);
+            }
+        }
+
+        return true;
+    }
 
-            if token.kind().is_open_paren() {
-                operator_stack.push(token.clone())
+    /// Reports `token` following another operand. After a name that is not
+    /// defined, as in `sine 1`, the name is the likelier mistake.
+    fn unexpected_operand(&mut self, token: &Token) {
+        let previous = &self.tokens[self.position - 1];
+
+        if let TokenKind::Identifier(ident) = previous.kind() {
+            if let Err(error) = self.registry.resolve(ident) {
+                return self.report(error, previous.span());
             }
+        }
 
-            if token.kind().is_close_paren() {
-                let mut top: &Token = &operator_stack[operator_stack.len() - 1]; //<- o2
+        self.report(
+            ParseError::UnexpectedToken(token.kind().clone()),
+            token.span(),
+        );
+    }
 
-                while !top.kind().is_open_paren() {
-                    if operator_stack.is_empty() {
-                        return Err(ParseError::ParensMismatch);
-                    }
-                    output_queue.push_front(operator_stack.pop().unwrap());
-                    top = &operator_stack[operator_stack.len().saturating_sub(1)];
+    /// Folds a run of signs into one operator.
+    // - + => error
+    // + - => -
+    // - - => +
+    fn additive_operator(&mut self) -> TokenKind {
+        let mut operator = self.tokens[self.position].kind().clone();
+        self.position += 1;
+
+        while let Some(token) = self.peek() {
+            operator = match (&operator, token.kind()) {
+                (TokenKind::Minus, TokenKind::Minus) => TokenKind::Plus,
+                (TokenKind::Plus, TokenKind::Minus) => TokenKind::Minus,
+                (_, TokenKind::Plus) => {
+                    self.report(
+                        ParseError::UnexpectedOperator(TokenKind::Plus),
+                        token.span(),
+                    );
+                    operator
                 }
+                _ => break,
+            };
+            self.position += 1;
+        }
 
-                if !operator_stack[operator_stack.len() - 1]
-                    .kind()
-                    .is_open_paren()
-                {
-                    return Err(ParseError::ParensMismatch);
-                }
+        return operator;
+    }
 
-                operator_stack.pop();
+    /// Applies the pending operators that bind at least as tightly as
+    /// `precedence`, or more tightly if the next operator groups to the
+    /// right, stopping at a parenthesis or skipped input.
+    fn reduce(&mut self, precedence: usize, right: bool) {
+        while let Some(top) = self.pending.last() {
+            let (Pending::Binary(kind) | Pending::Call(kind, _)) = top else {
+                return;
+            };
+            let top_precedence = kind.precedence().unwrap_or_default();
+
+            if top_precedence < precedence || (right && top_precedence == precedence) {
+                return;
             }
-        }
 
-        while let Some(token) = operator_stack.pop() {
-            output_queue.push_front(token)
+            let operand = self
+                .operands
+                .pop()
+                .unwrap_or_else(|| Expr::error(Span::default()));
+
+            let expr = match self.pending.pop() {
+                Some(Pending::Binary(kind)) => {
+                    let lhs = self
+                        .operands
+                        .pop()
+                        .unwrap_or_else(|| Expr::error(Span::default()));
+                    Expr::binary(kind, lhs, operand)
+                }
+                Some(Pending::Call(kind, span)) => {
+                    let span = span.to(operand.span());
+                    Expr::new(ExprKind::Call(kind, Box::new(operand)), span)
+                }
+                _ => return,
+            };
+
+            self.operands.push(expr);
         }
+    }
 
-        return Ok(output_queue);
+    /// Spans the operand of the group opened at `open` from there to `close`,
+    /// or to its own end if the group was never closed.
+    fn close_group(&mut self, open: Span, close: Option<Span>) {
+        let inner = self.operands.pop().unwrap_or_else(|| Expr::error(open));
+        let end = close.unwrap_or(inner.span());
+
+        self.operands
+            .push(Expr::new(inner.into_kind(), open.to(end)));
     }
 }
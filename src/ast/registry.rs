@@ -0,0 +1,257 @@
+#![allow(clippy::needless_return)]
+
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+
+use super::{lexer::TokenKind, parser::ParseError, statement::Function};
+
+/// Built-in functions, matched case-insensitively by the lexer.
+pub const FUNCTIONS: &[(&str, TokenKind)] = &[
+    ("sin", TokenKind::Sin),
+    ("cos", TokenKind::Cos),
+    ("tan", TokenKind::Tan),
+    ("log", TokenKind::Log),
+];
+
+/// Built-in constants, matched case-insensitively by the lexer.
+pub const CONSTANTS: &[(&str, TokenKind)] = &[("pi", TokenKind::Pi)];
+
+/// Signature and one-line description of every built-in, for `describe`.
+const DOCS: &[(&str, &str, &str)] = &[
+    ("sin", "sin x", "Sine of the angle x"),
+    ("cos", "cos x", "Cosine of the angle x"),
+    ("tan", "tan x", "Tangent of the angle x"),
+    ("log", "log x", "Base 10 logarithm of x, for x > 0"),
+    (
+        "pi",
+        "pi",
+        "Ratio of a circle's circumference to its diameter",
+    ),
+];
+
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Looks up a built-in function or constant by name.
+pub fn builtin(name: &str) -> Option<TokenKind> {
+    let name = name.to_lowercase();
+
+    return FUNCTIONS
+        .iter()
+        .chain(CONSTANTS)
+        .find(|(builtin, _)| *builtin == name)
+        .map(|(_, kind)| kind.clone());
+}
+
+/// Signature and description of a built-in function or constant.
+pub fn describe(name: &str) -> Option<(&'static str, &'static str)> {
+    let name = name.to_lowercase();
+
+    return DOCS
+        .iter()
+        .find(|(builtin, ..)| *builtin == name)
+        .map(|(_, signature, doc)| (*signature, *doc));
+}
+
+/// Variable holding the last recorded result.
+pub const ANSWER: &str = "ans";
+
+/// Whether `name` could be written in an expression and would not be
+/// shadowed by a built-in.
+fn is_valid_name(name: &str) -> bool {
+    return !name.is_empty() && name.chars().all(char::is_alphabetic) && builtin(name).is_none();
+}
+
+/// Names the evaluator can resolve: the built-ins plus user variables,
+/// user functions and recorded results.
+///
+/// Clones share their contents until one of them is changed, so copying a
+/// registry to try something out costs next to nothing.
+#[derive(Debug, Default, Clone)]
+pub struct Registry {
+    variables: Arc<BTreeMap<String, f32>>,
+    functions: Arc<BTreeMap<String, Function>>,
+    results: Arc<Vec<f32>>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    /// A user variable, or a recorded result for `$n`.
+    pub fn variable(&self, name: &str) -> Option<f32> {
+        if let Some(index) = name.strip_prefix('$') {
+            let index: usize = index.parse().ok()?;
+
+            return self.results.get(index.checked_sub(1)?).copied();
+        }
+
+        return self.variables.get(name).copied();
+    }
+
+    /// Like `variable`, but an unknown name becomes an error carrying suggestions.
+    pub fn resolve(&self, name: &str) -> Result<f32, ParseError> {
+        return self
+            .variable(name)
+            .ok_or_else(|| ParseError::UnknownIdentifier(name.to_string(), self.suggest(name)));
+    }
+
+    pub fn variables(&self) -> impl Iterator<Item = (&str, f32)> {
+        return self
+            .variables
+            .iter()
+            .map(|(name, value)| (name.as_str(), *value));
+    }
+
+    /// Fails if `name` could not be written in an expression or would be
+    /// shadowed by a built-in.
+    pub fn set_variable(&mut self, name: &str, value: f32) -> Result<(), String> {
+        if !is_valid_name(name) {
+            return Err(name.to_string());
+        }
+
+        Arc::make_mut(&mut self.variables).insert(name.to_string(), value);
+
+        return Ok(());
+    }
+
+    pub fn remove_variable(&mut self, name: &str) -> Option<f32> {
+        return Arc::make_mut(&mut self.variables).remove(name);
+    }
+
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        return self.functions.get(name);
+    }
+
+    pub fn functions(&self) -> impl Iterator<Item = &Function> {
+        return self.functions.values();
+    }
+
+    /// Defines or replaces a function. Fails with the first name, of the
+    /// function or a parameter, that a variable could not have, or with a
+    /// parameter given twice.
+    pub fn define_function(&mut self, function: Function) -> Result<(), String> {
+        let mut names = core::iter::once(function.name())
+            .chain(function.parameters().iter().map(String::as_str));
+
+        if let Some(name) = names.find(|name| !is_valid_name(name)) {
+            return Err(name.to_string());
+        }
+
+        let parameters = function.parameters();
+        for (index, parameter) in parameters.iter().enumerate() {
+            if parameters[..index].contains(parameter) {
+                return Err(parameter.clone());
+            }
+        }
+
+        Arc::make_mut(&mut self.functions).insert(function.name().to_string(), function);
+
+        return Ok(());
+    }
+
+    pub fn remove_function(&mut self, name: &str) -> Option<Function> {
+        return Arc::make_mut(&mut self.functions).remove(name);
+    }
+
+    /// Stores `value` as the next `$n` and as `ans`, returning `n`.
+    pub fn record(&mut self, value: f32) -> usize {
+        Arc::make_mut(&mut self.results).push(value);
+        Arc::make_mut(&mut self.variables).insert(ANSWER.to_string(), value);
+
+        return self.results.len();
+    }
+
+    /// Recorded results, `$1` first.
+    pub fn results(&self) -> &[f32] {
+        return &self.results;
+    }
+
+    /// Every name an expression can use: the built-ins, then user
+    /// variables and functions.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        return FUNCTIONS
+            .iter()
+            .chain(CONSTANTS)
+            .map(|(builtin, _)| *builtin)
+            .chain(self.variables.keys().map(String::as_str))
+            .chain(self.functions.keys().map(String::as_str));
+    }
+
+    /// Known names starting with `prefix`, sorted. Built-ins match
+    /// regardless of case, as they do in the lexer.
+    pub fn complete(&self, prefix: &str) -> Vec<&str> {
+        let lowercase = prefix.to_lowercase();
+
+        let mut names: Vec<&str> = self
+            .names()
+            .filter(|name| match builtin(name) {
+                Some(_) => name.starts_with(&lowercase),
+                None => name.starts_with(prefix),
+            })
+            .collect();
+
+        names.sort_unstable();
+        names.dedup();
+
+        return names;
+    }
+
+    /// Known names closest to `name` by edit distance, best match first.
+    pub fn suggest(&self, name: &str) -> Vec<String> {
+        let name = name.to_lowercase();
+        let max_distance = name.chars().count().div_ceil(3);
+
+        let mut candidates: Vec<(usize, &str)> = self
+            .names()
+            .map(|candidate| (edit_distance(&name, &candidate.to_lowercase()), candidate))
+            .filter(|(distance, _)| *distance <= max_distance)
+            .collect();
+
+        candidates.sort();
+
+        return candidates
+            .into_iter()
+            .take(MAX_SUGGESTIONS)
+            .map(|(_, candidate)| candidate.to_string())
+            .collect();
+    }
+}
+
+/// Optimal string alignment distance, so a swapped pair of letters counts as one edit.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+
+            let mut distance = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(rows[i - 2][j - 2] + 1);
+            }
+
+            rows[i][j] = distance;
+        }
+    }
+
+    return rows[a.len()][b.len()];
+}
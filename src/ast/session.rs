@@ -0,0 +1,220 @@
+#![allow(clippy::needless_return)]
+
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{fmt::Write, mem};
+
+use super::{
+    console_error::ConsoleError,
+    interpreter::Interpreter,
+    parser::{AngleUnit, FloatPolicy, ParseError},
+    registry::Registry,
+    statement::{self, Function},
+};
+
+/// First line of a saved session.
+const HEADER: &str = "# arth_lexer session";
+
+/// Functions ordered so that each comes after the ones it calls, as they
+/// have to be defined in that order.
+fn definition_order(registry: &Registry) -> Vec<&Function> {
+    let mut ordered = Vec::new();
+
+    for function in registry.functions() {
+        add_after_callees(function, registry, &mut ordered);
+    }
+
+    return ordered;
+}
+
+// definitions can not form a cycle, so this always ends
+fn add_after_callees<'r>(
+    function: &'r Function,
+    registry: &'r Registry,
+    ordered: &mut Vec<&'r Function>,
+) {
+    if ordered.iter().any(|done| done.name() == function.name()) {
+        return;
+    }
+
+    for callee in registry.functions() {
+        if callee.name() != function.name() && statement::calls(function.body(), callee.name()) {
+            add_after_callees(callee, registry, ordered);
+        }
+    }
+
+    ordered.push(function);
+}
+
+impl Interpreter {
+    /// The variables, functions, modes, number format and recorded results
+    /// as text that `load_session` reads back, one per line:
+    ///
+    /// ```text
+    /// # arth_lexer session
+    /// :mode strict rad
+    /// :format auto
+    /// $1 = 4.0
+    /// ans = 4.0
+    /// r = 2.5
+    /// area(r) = pi * r * r
+    /// ```
+    ///
+    /// Values are written so they read back exactly.
+    pub fn save_session(&self) -> String {
+        let mut session = String::new();
+        let registry = self.registry();
+
+        let _ = writeln!(session, "{HEADER}");
+        let _ = writeln!(
+            session,
+            ":mode {} {}",
+            self.float_policy().name(),
+            self.angle_unit().name()
+        );
+        let _ = writeln!(session, ":format {}", self.number_format());
+
+        for (index, value) in registry.results().iter().enumerate() {
+            let _ = writeln!(session, "${} = {value:?}", index + 1);
+        }
+        for (name, value) in registry.variables() {
+            let _ = writeln!(session, "{name} = {value:?}");
+        }
+        for function in definition_order(registry) {
+            let _ = writeln!(session, "{function}");
+        }
+
+        return session;
+    }
+
+    /// Replaces the variables, functions, modes, number format and recorded
+    /// results with those of a session written by `save_session`. The
+    /// session is read like `run_script` reads a script.
+    ///
+    /// Nothing changes if a line fails, the error tells which one.
+    pub fn load_session(&mut self, session: &str) -> Result<(), ConsoleError> {
+        let mut loaded = Interpreter::new();
+        loaded.set_limits(*self.limits());
+        loaded.set_optimize(self.optimize());
+        loaded.run_script(session)?;
+
+        // everything was checked above, so none of this fails
+        self.reset();
+        self.set_float_policy(loaded.float_policy());
+        self.set_angle_unit(loaded.angle_unit());
+        self.set_number_format(loaded.number_format());
+
+        for value in loaded.results() {
+            self.record_result(*value);
+        }
+        for (name, value) in loaded.registry().variables() {
+            self.set_variable(name, value)?;
+        }
+        for function in definition_order(loaded.registry()) {
+            self.run(&function.to_string())?;
+        }
+
+        return Ok(());
+    }
+
+    /// Runs a script of statements, such as definitions shared by a team,
+    /// stopping at the first line that fails. Blank lines and lines
+    /// starting with `#` are skipped, `:mode` and `:format` set the modes
+    /// and number format like in the console, and a line that leaves
+    /// parentheses open goes on in the next.
+    pub fn run_script(&mut self, script: &str) -> Result<(), ConsoleError> {
+        let mut pending = String::new();
+        let mut first = 0;
+
+        for (index, line) in script.lines().enumerate() {
+            if pending.is_empty() {
+                first = index + 1;
+            } else {
+                pending.push('\n');
+            }
+            pending.push_str(line.trim());
+
+            if statement::is_incomplete(&pending) {
+                continue;
+            }
+
+            let line = mem::take(&mut pending);
+            self.run_line(&line)
+                .map_err(|err| ConsoleError::Line(first, Box::new(err)))?;
+        }
+
+        if !pending.is_empty() {
+            self.run_line(&pending)
+                .map_err(|err| ConsoleError::Line(first, Box::new(err)))?;
+        }
+
+        return Ok(());
+    }
+
+    fn run_line(&mut self, line: &str) -> Result<(), ConsoleError> {
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(());
+        }
+
+        if let Some(settings) = line.strip_prefix(":mode") {
+            for setting in settings.split_whitespace() {
+                if let Some(policy) = FloatPolicy::from_name(setting) {
+                    self.set_float_policy(policy);
+                } else if let Some(unit) = AngleUnit::from_name(setting) {
+                    self.set_angle_unit(unit);
+                } else {
+                    return Err(ConsoleError::UnknownSetting(setting.to_string()));
+                }
+            }
+
+            return Ok(());
+        }
+
+        if let Some(settings) = line.strip_prefix(":format") {
+            let mut number_format = self.number_format();
+            for setting in settings.split_whitespace() {
+                number_format.set(setting)?;
+            }
+            self.set_number_format(number_format);
+
+            return Ok(());
+        }
+
+        if line.starts_with(':') {
+            let command = line.split_whitespace().next().unwrap_or(line);
+            return Err(ConsoleError::UnknownCommand(command.to_string()));
+        }
+
+        if let Some((name, value)) = line.split_once('=') {
+            let (name, value) = (name.trim(), value.trim());
+
+            if let Some(index) = name.strip_prefix('$') {
+                let value = value
+                    .parse()
+                    .map_err(|_| ParseError::InvalidNumber(value.to_string()))?;
+
+                // results are numbered by the order they were recorded in
+                if index.parse() != Ok(self.results().len() + 1) {
+                    return Err(ParseError::InvalidIdentifier(name.to_string()).into());
+                }
+                self.record_result(value);
+
+                return Ok(());
+            }
+
+            // also reads back `inf`, `NaN` and `1e20`, which are not
+            // expressions, but leaves definitions such as `c(x) = 2` to `run`
+            let variable = !name.is_empty() && name.chars().all(char::is_alphabetic);
+            if let (true, Ok(value)) = (variable, value.parse()) {
+                return Ok(self.set_variable(name, value)?);
+            }
+        }
+
+        self.run(line)?;
+
+        return Ok(());
+    }
+}
@@ -0,0 +1,425 @@
+#![allow(clippy::needless_return)]
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt;
+
+use super::{
+    lexer::{Lexer, Span, Token, TokenKind},
+    limits::{Deadline, Limit, Limits},
+    parser::{Diagnostic, ParseError},
+    registry::Registry,
+};
+
+/// Deepest nesting of calls `expand` follows, whatever the limits say, so
+/// that it cannot run out of stack.
+const MAX_EXPANSION_DEPTH: usize = 256;
+
+/// A function defined with `name(parameters) = body`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    name: String,
+    parameters: Vec<String>,
+    body: Vec<Token>,
+    source: String,
+}
+
+impl Function {
+    /// `source` is the text of `body`, kept to show the definition.
+    pub fn new(name: String, parameters: Vec<String>, body: Vec<Token>, source: String) -> Self {
+        return Self {
+            name,
+            parameters,
+            body,
+            source,
+        };
+    }
+
+    pub fn name(&self) -> &str {
+        return &self.name;
+    }
+
+    pub fn parameters(&self) -> &[String] {
+        return &self.parameters;
+    }
+
+    pub fn body(&self) -> &[Token] {
+        return &self.body;
+    }
+
+    pub fn source(&self) -> &str {
+        return &self.source;
+    }
+}
+
+/// The definition as it would be written, `f(x, y) = x * y`.
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}({}) = {}",
+            self.name,
+            self.parameters.join(", "),
+            self.source
+        )
+    }
+}
+
+/// One statement of the input to `Interpreter::run`.
+#[derive(Debug, PartialEq)]
+pub enum Statement {
+    Expression(Vec<Token>),
+    /// `name = expression`
+    Assignment(String, Vec<Token>),
+    Definition(Function),
+}
+
+/// The part of `source` that `span`, in characters, covers.
+pub fn text(source: &str, span: Span) -> &str {
+    let offset = |chars: usize| {
+        source
+            .char_indices()
+            .nth(chars)
+            .map_or(source.len(), |(index, _)| index)
+    };
+
+    return &source[offset(span.start)..offset(span.end)];
+}
+
+/// Span from the first to the last of `tokens`.
+pub fn span_of(tokens: &[Token]) -> Span {
+    match (tokens.first(), tokens.last()) {
+        (Some(first), Some(last)) => return first.span().to(last.span()),
+        _ => return Span::default(),
+    }
+}
+
+/// Splits `tokens` at semicolons, and at newlines outside parentheses,
+/// leaving out empty statements.
+pub fn split(tokens: Vec<Token>) -> Vec<Vec<Token>> {
+    let mut statements = Vec::new();
+    let mut current = Vec::new();
+    let mut depth = 0usize;
+
+    for token in tokens {
+        match token.kind() {
+            TokenKind::OpenParen => depth += 1,
+            TokenKind::CloseParen => depth = depth.saturating_sub(1),
+            TokenKind::Newline if depth > 0 => continue,
+            TokenKind::Semicolon | TokenKind::Newline => {
+                if !current.is_empty() {
+                    statements.push(core::mem::take(&mut current));
+                }
+                depth = 0;
+                continue;
+            }
+            _ => {}
+        }
+
+        current.push(token);
+    }
+
+    if !current.is_empty() {
+        statements.push(current);
+    }
+
+    return statements;
+}
+
+/// Whether `source` opens more parentheses than it closes, so the input
+/// goes on in the next line.
+pub fn is_incomplete(source: &str) -> bool {
+    let (tokens, _) = Lexer::new().lex_all(source);
+    let mut depth = 0usize;
+
+    for token in &tokens {
+        match token.kind() {
+            TokenKind::OpenParen => depth += 1,
+            TokenKind::CloseParen => depth = depth.saturating_sub(1),
+            TokenKind::Semicolon => depth = 0,
+            _ => {}
+        }
+    }
+
+    return depth > 0;
+}
+
+fn identifier(token: &Token) -> Option<&str> {
+    match token.kind() {
+        TokenKind::Identifier(name) => return Some(name),
+        _ => return None,
+    }
+}
+
+/// Names in `name(a, b)`, or `None` if `tokens` are not of that shape.
+fn signature(tokens: &[Token]) -> Option<(String, Vec<String>)> {
+    let [name, open, inner @ .., close] = tokens else {
+        return None;
+    };
+
+    if !open.kind().is_open_paren() || !close.kind().is_close_paren() {
+        return None;
+    }
+
+    let mut parameters = Vec::new();
+
+    for (index, token) in inner.iter().enumerate() {
+        if index % 2 == 0 {
+            parameters.push(identifier(token)?.to_string());
+        } else if !matches!(token.kind(), TokenKind::Comma) {
+            return None;
+        }
+    }
+
+    if inner.len() % 2 == 0 && !inner.is_empty() {
+        return None;
+    }
+
+    return Some((identifier(name)?.to_string(), parameters));
+}
+
+impl Statement {
+    /// Tells assignments and definitions apart from expressions by the `=`
+    /// in them. `source` is the text `tokens` were lexed from.
+    pub fn classify(tokens: Vec<Token>, source: &str) -> Result<Statement, Diagnostic> {
+        let Some(equals) = tokens
+            .iter()
+            .position(|token| matches!(token.kind(), TokenKind::Equals))
+        else {
+            return Ok(Statement::Expression(tokens));
+        };
+
+        let (target, value) = (&tokens[..equals], &tokens[equals + 1..]);
+
+        if target.is_empty() {
+            return Err(Diagnostic::new(
+                ParseError::UnexpectedToken(TokenKind::Equals),
+                tokens[equals].span(),
+            ));
+        }
+        if value.is_empty() {
+            let end = tokens[equals].span().end;
+            return Err(Diagnostic::new(
+                ParseError::MissingExpression,
+                Span::new(end, end),
+            ));
+        }
+
+        if let [name] = target {
+            if let Some(name) = identifier(name) {
+                return Ok(Statement::Assignment(name.to_string(), value.to_vec()));
+            }
+        }
+
+        if let Some((name, parameters)) = signature(target) {
+            // on one line, even if it was written over several
+            let body = text(source, span_of(value))
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ");
+
+            return Ok(Statement::Definition(Function::new(
+                name,
+                parameters,
+                value.to_vec(),
+                body,
+            )));
+        }
+
+        let span = span_of(target).to(tokens[equals].span());
+        let target = text(source, span_of(target)).to_string();
+
+        return Err(Diagnostic::new(ParseError::InvalidIdentifier(target), span));
+    }
+}
+
+/// Index of the `)` closing the `(` at `open`.
+fn closing_paren(tokens: &[Token], open: usize) -> Option<usize> {
+    let mut depth = 0usize;
+
+    for (index, token) in tokens.iter().enumerate().skip(open) {
+        match token.kind() {
+            TokenKind::OpenParen => depth += 1,
+            TokenKind::CloseParen if depth == 1 => return Some(index),
+            TokenKind::CloseParen => depth -= 1,
+            _ => {}
+        }
+    }
+
+    return None;
+}
+
+/// The arguments between the parentheses of a call, split at commas
+/// outside nested parentheses.
+fn arguments(tokens: &[Token]) -> Vec<&[Token]> {
+    if tokens.is_empty() {
+        return Vec::new();
+    }
+
+    let mut arguments = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+
+    for (index, token) in tokens.iter().enumerate() {
+        match token.kind() {
+            TokenKind::OpenParen => depth += 1,
+            TokenKind::CloseParen => depth = depth.saturating_sub(1),
+            TokenKind::Comma if depth == 0 => {
+                arguments.push(&tokens[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+
+    arguments.push(&tokens[start..]);
+
+    return arguments;
+}
+
+/// Replaces every call of a user function in `tokens` with its body in
+/// parentheses, each parameter replaced by its argument in parentheses.
+/// The tokens that come from the body take the span of the call. Fails as
+/// soon as the expansion grows past `Limits::max_tokens` or the deadline.
+pub fn expand(
+    tokens: &[Token],
+    registry: &Registry,
+    limits: &Limits,
+    deadline: &Deadline,
+) -> Result<Vec<Token>, Diagnostic> {
+    return expand_calls(tokens, registry, limits, deadline, &mut Vec::new(), 0);
+}
+
+/// Expands the calls in the body of `function`, failing if any of them
+/// lead back to it, as they could through an older definition of its name.
+pub fn expand_body(
+    function: &Function,
+    registry: &Registry,
+    limits: &Limits,
+    deadline: &Deadline,
+) -> Result<Vec<Token>, Diagnostic> {
+    let mut calling = alloc::vec![function.name().to_string()];
+
+    return expand_calls(function.body(), registry, limits, deadline, &mut calling, 0);
+}
+
+/// `calling` holds the functions whose bodies are being expanded, `depth`
+/// counts them along with the calls whose arguments are.
+fn expand_calls(
+    tokens: &[Token],
+    registry: &Registry,
+    limits: &Limits,
+    deadline: &Deadline,
+    calling: &mut Vec<String>,
+    depth: usize,
+) -> Result<Vec<Token>, Diagnostic> {
+    let mut expanded = Vec::with_capacity(tokens.len());
+    let mut index = 0;
+
+    while index < tokens.len() {
+        let token = &tokens[index];
+        let name = match tokens.get(index + 1).map(Token::kind) {
+            Some(TokenKind::OpenParen) => identifier(token),
+            _ => None,
+        };
+
+        let Some(name) = name else {
+            expanded.push(token.clone());
+            index += 1;
+            continue;
+        };
+
+        // left alone, `name(x)` would quietly evaluate to `x`
+        let Some(function) = registry.function(name) else {
+            return Err(Diagnostic::new(
+                ParseError::UnknownIdentifier(name.to_string(), registry.suggest(name)),
+                token.span(),
+            ));
+        };
+
+        let Some(close) = closing_paren(tokens, index + 1) else {
+            return Err(Diagnostic::new(
+                ParseError::ParensMismatch,
+                tokens[index + 1].span(),
+            ));
+        };
+        let span = token.span().to(tokens[close].span());
+        let fail = |error: ParseError| Diagnostic::new(error, span);
+        // each use of a parameter copies its argument, so nested calls grow
+        // exponentially and are stopped while they do rather than after
+        let grown = |count: usize| {
+            limits
+                .check_token_count(count)
+                .map_err(ParseError::LimitExceeded)
+                .and_then(|()| deadline.check())
+                .map_err(fail)
+        };
+
+        let arguments = arguments(&tokens[index + 2..close]);
+        let parameters = function.parameters();
+
+        if arguments.len() != parameters.len() {
+            return Err(fail(ParseError::ArgumentCount(
+                function.name().to_string(),
+                parameters.len(),
+                arguments.len(),
+            )));
+        }
+        if calling.iter().any(|name| name == function.name()) {
+            return Err(fail(ParseError::RecursiveFunction(
+                function.name().to_string(),
+            )));
+        }
+        if depth >= MAX_EXPANSION_DEPTH {
+            return Err(fail(ParseError::LimitExceeded(Limit::CallDepth(
+                MAX_EXPANSION_DEPTH,
+            ))));
+        }
+        limits
+            .check_call_depth(depth + 1)
+            .map_err(|limit| fail(ParseError::LimitExceeded(limit)))?;
+
+        let arguments = arguments
+            .into_iter()
+            .map(|argument| expand_calls(argument, registry, limits, deadline, calling, depth + 1))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut body = Vec::with_capacity(function.body().len());
+        for token in function.body() {
+            let parameter = identifier(token)
+                .and_then(|name| parameters.iter().position(|parameter| parameter == name));
+
+            match parameter {
+                Some(position) => {
+                    body.push(Token::spanned(TokenKind::OpenParen, span));
+                    body.extend(arguments[position].iter().cloned());
+                    body.push(Token::spanned(TokenKind::CloseParen, span));
+                    grown(body.len())?;
+                }
+                None => body.push(Token::spanned(token.kind().clone(), span)),
+            }
+        }
+
+        calling.push(function.name().to_string());
+        let body = expand_calls(&body, registry, limits, deadline, calling, depth + 1)?;
+        calling.pop();
+
+        expanded.push(Token::spanned(TokenKind::OpenParen, span));
+        expanded.extend(body);
+        expanded.push(Token::spanned(TokenKind::CloseParen, span));
+        grown(expanded.len())?;
+
+        index = close + 1;
+    }
+
+    return Ok(expanded);
+}
+
+/// Whether `body` calls `name` directly.
+pub fn calls(body: &[Token], name: &str) -> bool {
+    return body
+        .windows(2)
+        .any(|pair| identifier(&pair[0]) == Some(name) && pair[1].kind().is_open_paren());
+}
@@ -0,0 +1,109 @@
+#![allow(clippy::needless_return)]
+
+use alloc::{string::String, sync::Arc};
+use core::fmt;
+
+/// Verbosity of an `Event`, from most to least important.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Level {
+    #[default]
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    pub fn from_name(name: &str) -> Option<Level> {
+        let level = match name.to_lowercase().as_str() {
+            "off" => Level::Off,
+            "error" => Level::Error,
+            "warn" => Level::Warn,
+            "info" => Level::Info,
+            "debug" => Level::Debug,
+            "trace" => Level::Trace,
+            _ => return None,
+        };
+
+        return Some(level);
+    }
+}
+
+/// The part of the pipeline an `Event` comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Lexer,
+    Parser,
+    Evaluator,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Stage::Lexer => "lexer",
+            Stage::Parser => "parser",
+            Stage::Evaluator => "evaluator",
+        };
+
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Debug)]
+pub struct Event {
+    pub level: Level,
+    pub stage: Stage,
+    pub message: String,
+}
+
+pub type Subscriber = Arc<dyn Fn(&Event) + Send + Sync>;
+
+/// Forwards events at or above a level to a subscriber. Without one nothing
+/// is formatted or printed, so library callers get silence by default.
+#[derive(Clone, Default)]
+pub struct Tracer {
+    subscriber: Option<Subscriber>,
+    level: Level,
+}
+
+impl fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tracer")
+            .field("level", &self.level)
+            .field("subscribed", &self.subscriber.is_some())
+            .finish()
+    }
+}
+
+impl Tracer {
+    pub fn new(level: Level, subscriber: Subscriber) -> Self {
+        return Self {
+            subscriber: Some(subscriber),
+            level,
+        };
+    }
+
+    pub fn enabled(&self, level: Level) -> bool {
+        return self.subscriber.is_some() && level != Level::Off && level <= self.level;
+    }
+
+    /// `message` is only called when the event will actually be delivered.
+    pub fn emit<F>(&self, level: Level, stage: Stage, message: F)
+    where
+        F: FnOnce() -> String,
+    {
+        if !self.enabled(level) {
+            return;
+        }
+
+        if let Some(subscriber) = &self.subscriber {
+            subscriber(&Event {
+                level,
+                stage,
+                message: message(),
+            });
+        }
+    }
+}
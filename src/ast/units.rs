@@ -0,0 +1,189 @@
+#![allow(clippy::needless_return)]
+
+use core::{f64::consts::PI, fmt};
+
+use super::{lexer::TokenKind, math::double as math, number::Number, parser::ParseError};
+
+/// Exponents of the SI base units, in the order of `BASE_UNITS`.
+type Dimension = [i8; 7];
+
+const DIMENSIONLESS: Dimension = [0; 7];
+
+const BASE_UNITS: [&str; 7] = ["m", "kg", "s", "A", "K", "mol", "cd"];
+
+/// Units an expression can name, with their size in base units.
+pub const UNITS: &[(&str, f64, Dimension)] = &[
+    ("m", 1.0, [1, 0, 0, 0, 0, 0, 0]),
+    ("km", 1e3, [1, 0, 0, 0, 0, 0, 0]),
+    ("cm", 1e-2, [1, 0, 0, 0, 0, 0, 0]),
+    ("mm", 1e-3, [1, 0, 0, 0, 0, 0, 0]),
+    ("kg", 1.0, [0, 1, 0, 0, 0, 0, 0]),
+    ("g", 1e-3, [0, 1, 0, 0, 0, 0, 0]),
+    ("s", 1.0, [0, 0, 1, 0, 0, 0, 0]),
+    ("ms", 1e-3, [0, 0, 1, 0, 0, 0, 0]),
+    ("min", 60.0, [0, 0, 1, 0, 0, 0, 0]),
+    ("h", 3600.0, [0, 0, 1, 0, 0, 0, 0]),
+    ("A", 1.0, [0, 0, 0, 1, 0, 0, 0]),
+    ("K", 1.0, [0, 0, 0, 0, 1, 0, 0]),
+    ("mol", 1.0, [0, 0, 0, 0, 0, 1, 0]),
+    ("cd", 1.0, [0, 0, 0, 0, 0, 0, 1]),
+    ("Hz", 1.0, [0, 0, -1, 0, 0, 0, 0]),
+    ("N", 1.0, [1, 1, -2, 0, 0, 0, 0]),
+    ("Pa", 1.0, [-1, 1, -2, 0, 0, 0, 0]),
+    ("J", 1.0, [2, 1, -2, 0, 0, 0, 0]),
+    ("W", 1.0, [2, 1, -3, 0, 0, 0, 0]),
+    ("C", 1.0, [0, 0, 1, 1, 0, 0, 0]),
+    ("V", 1.0, [2, 1, -3, -1, 0, 0, 0]),
+];
+
+fn unit(name: &str) -> Option<Quantity> {
+    return UNITS
+        .iter()
+        .find(|(unit, ..)| *unit == name)
+        .map(|(_, value, dimension)| Quantity {
+            value: *value,
+            dimension: *dimension,
+        });
+}
+
+/// An `f64` in SI base units along with its dimension, written with the
+/// names in `UNITS` such as `9.81 * m / s ^ 2`. Only quantities of the
+/// same dimension can be added, and functions take dimensionless ones.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quantity {
+    value: f64,
+    dimension: Dimension,
+}
+
+impl Quantity {
+    /// The value in SI base units.
+    pub fn value(&self) -> f64 {
+        return self.value;
+    }
+
+    pub fn is_dimensionless(&self) -> bool {
+        return self.dimension == DIMENSIONLESS;
+    }
+
+    /// The value in one of `UNITS`, if it has the same dimension.
+    pub fn value_in(&self, name: &str) -> Option<f64> {
+        let unit = unit(name)?;
+
+        return (unit.dimension == self.dimension).then(|| self.value / unit.value);
+    }
+
+    fn dimensionless(value: f64) -> Self {
+        return Self {
+            value,
+            dimension: DIMENSIONLESS,
+        };
+    }
+
+    /// Adds up the exponents of both, or subtracts those of `rhs`.
+    fn combine(&self, rhs: &Self, sign: i8) -> Option<Dimension> {
+        let mut dimension = self.dimension;
+        for (exponent, other) in dimension.iter_mut().zip(rhs.dimension) {
+            *exponent = exponent.checked_add(other.checked_mul(sign)?)?;
+        }
+
+        return Some(dimension);
+    }
+}
+
+/// The value followed by the base units, `9.81 m s^-2`.
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.value)?;
+
+        for (unit, exponent) in BASE_UNITS.iter().zip(self.dimension) {
+            match exponent {
+                0 => {}
+                1 => write!(f, " {unit}")?,
+                exponent => write!(f, " {unit}^{exponent}")?,
+            }
+        }
+
+        return Ok(());
+    }
+}
+
+impl Number for Quantity {
+    fn parse_literal(literal: &str) -> Option<Self> {
+        return literal.parse().ok().map(Self::dimensionless);
+    }
+
+    fn from_f32(value: f32) -> Option<Self> {
+        return Some(Self::dimensionless(f64::from(value)));
+    }
+
+    fn named(name: &str) -> Option<Self> {
+        return unit(name);
+    }
+
+    fn pi() -> Option<Self> {
+        return Some(Self::dimensionless(PI));
+    }
+
+    fn binary(&self, operator: &TokenKind, rhs: &Self) -> Result<Self, ParseError> {
+        let incompatible = || ParseError::IncompatibleUnits(self.written(operator, rhs));
+
+        let (value, dimension) = match operator {
+            TokenKind::Plus | TokenKind::Minus if self.dimension != rhs.dimension => {
+                return Err(incompatible());
+            }
+            TokenKind::Plus => (self.value + rhs.value, Some(self.dimension)),
+            TokenKind::Minus => (self.value - rhs.value, Some(self.dimension)),
+            TokenKind::Asterix => (self.value * rhs.value, self.combine(rhs, 1)),
+            TokenKind::Slash if rhs.value == 0.0 => return Err(ParseError::DivisionByZero),
+            TokenKind::Slash => (self.value / rhs.value, self.combine(rhs, -1)),
+            TokenKind::Power => {
+                // a unit can only be raised to a whole power
+                let whole = rhs.value % 1.0 == 0.0 && rhs.value.abs() <= f64::from(i8::MAX);
+                if !rhs.is_dimensionless() || !(whole || self.is_dimensionless()) {
+                    return Err(incompatible());
+                }
+
+                let mut dimension = self.dimension;
+                for exponent in &mut dimension {
+                    *exponent = exponent
+                        .checked_mul(rhs.value as i8)
+                        .ok_or_else(incompatible)?;
+                }
+
+                (math::powf(self.value, rhs.value), Some(dimension))
+            }
+            unknown => return Err(ParseError::UnexpectedOperator(unknown.clone())),
+        };
+
+        let dimension = dimension.ok_or_else(incompatible)?;
+        if !value.is_finite() {
+            return Err(ParseError::NonFinite(self.written(operator, rhs)));
+        }
+
+        return Ok(Self { value, dimension });
+    }
+
+    fn apply(&self, function: &TokenKind) -> Result<Self, ParseError> {
+        if !self.is_dimensionless() {
+            return Err(ParseError::IncompatibleUnits(self.applied(function)));
+        }
+
+        let arg = self.value;
+        let (value, in_domain) = match function {
+            TokenKind::Sin => (math::sin(arg), arg.is_finite()),
+            TokenKind::Cos => (math::cos(arg), arg.is_finite()),
+            TokenKind::Tan => (math::tan(arg), arg.is_finite()),
+            TokenKind::Log => (math::log10(arg), arg > 0.0),
+            unknown => return Err(ParseError::UnexpectedOperator(unknown.clone())),
+        };
+
+        if !in_domain {
+            return Err(ParseError::OutOfDomain(self.applied(function)));
+        }
+        if !value.is_finite() {
+            return Err(ParseError::NonFinite(self.applied(function)));
+        }
+
+        return Ok(Self::dimensionless(value));
+    }
+}
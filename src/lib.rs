@@ -1,10 +1,86 @@
+//! Lexer, parser and evaluator for arithmetic expressions.
+//!
+//! ```
+//! assert_eq!(7.0, arth_lexer::eval("1 + 2 * 3").unwrap());
+//!
+//! let mut inter = arth_lexer::Interpreter::new();
+//! let area = inter.compile("pi * r ^ 2").unwrap();
+//!
+//! assert_eq!(std::f32::consts::PI * 4.0, area.eval(&[2.0]).unwrap());
+//!
+//! inter.set_variable("r", 3.0).unwrap();
+//! assert_eq!(std::f32::consts::PI * 9.0, inter.evaluate(&area).unwrap());
+//! ```
+
+#![cfg_attr(not(feature = "std"), no_std)]
+#![allow(clippy::needless_return)]
+
+#[cfg(not(any(feature = "std", feature = "libm")))]
+compile_error!("the core needs either the `std` or the `libm` feature for its math functions");
+
+extern crate alloc;
+
+#[cfg(all(test, not(feature = "std")))]
+extern crate std;
+
 mod ast;
 
+pub use ast::{
+    bytecode::{Instruction, Program},
+    cache::{CacheStats, ExpressionCache},
+    compiled::CompiledExpr,
+    console_error::ConsoleError,
+    expr::{Expr, ExprKind},
+    format::{Notation, NumberFormat},
+    interpreter::Interpreter,
+    lexer::{Lexer, Span, Token, TokenKind},
+    limits::{Limit, Limits},
+    number::Number,
+    parser::{AngleUnit, Diagnostic, FloatPolicy, ParseError, Parser},
+    registry::{describe, Registry, CONSTANTS, FUNCTIONS},
+    statement::{is_incomplete, Function},
+    trace::{Event, Level, Stage, Subscriber, Tracer},
+};
+
+#[cfg(feature = "repl")]
+pub use ast::config::Config;
+
+#[cfg(feature = "jit")]
+pub use ast::jit::JitExpr;
+
+#[cfg(feature = "units")]
+pub use ast::units::{Quantity, UNITS};
+
+#[cfg(feature = "bigint")]
+pub use num_bigint::BigInt;
+
+#[cfg(feature = "complex")]
+pub use num_complex::Complex64;
+
+#[cfg(feature = "decimal")]
+pub use rust_decimal::Decimal;
+
+/// Evaluates `expression` with a fresh `Interpreter`.
+pub fn eval(expression: &str) -> Result<f32, ParseError> {
+    return Interpreter::new().interpret(expression);
+}
+
 #[cfg(test)]
 mod tests {
-    use std::f32::consts::PI;
+    use std::{
+        f32::consts::PI,
+        format,
+        string::{String, ToString},
+        sync::{Arc, Mutex},
+        vec,
+        vec::Vec,
+    };
 
-    use crate::ast::interpreter::Interpreter;
+    use crate::{
+        AngleUnit, CacheStats, CompiledExpr, ConsoleError, Expr, ExprKind, FloatPolicy,
+        Instruction, Interpreter, Level, Lexer, Limit, Limits, Notation, NumberFormat, ParseError,
+        Parser, Span, Stage, TokenKind,
+    };
 
     #[test]
     fn test_calculation_1() {
@@ -260,4 +336,1834 @@ mod tests {
                 .unwrap()
         )
     }
+
+    #[test]
+    fn strict_policy_rejects_log_of_zero() {
+        let mut inter = Interpreter::new();
+
+        assert!(matches!(
+            inter.interpret("log 0 - log 0"),
+            Err(ParseError::OutOfDomain(_))
+        ))
+    }
+
+    #[test]
+    fn strict_policy_rejects_overflow() {
+        let mut inter = Interpreter::new();
+
+        assert!(matches!(
+            inter.interpret("10^100"),
+            Err(ParseError::NonFinite(_))
+        ))
+    }
+
+    #[test]
+    fn strict_policy_rejects_tan_pole() {
+        let mut inter = Interpreter::new();
+
+        assert!(matches!(
+            inter.interpret("tan 1.5707964"),
+            Err(ParseError::OutOfDomain(_))
+        ))
+    }
+
+    #[test]
+    fn ieee_policy_passes_through() {
+        let mut inter = Interpreter::new();
+        inter.set_float_policy(FloatPolicy::Ieee);
+
+        assert!(inter.interpret("log 0 - log 0").unwrap().is_nan());
+        assert_eq!(f32::INFINITY, inter.interpret("10^100").unwrap());
+        assert_eq!(f32::INFINITY, inter.interpret("1 / 0").unwrap());
+        assert!(inter.warnings().is_empty());
+    }
+
+    #[test]
+    fn warn_policy_records_warnings() {
+        let mut inter = Interpreter::new();
+        inter.set_float_policy(FloatPolicy::Warn);
+
+        assert!(inter.interpret("log 0 - log 0").unwrap().is_nan());
+        assert_eq!(2, inter.warnings().len());
+
+        assert_eq!(2.0, inter.interpret("1 + 1").unwrap());
+        assert!(inter.warnings().is_empty());
+    }
+
+    #[test]
+    fn stray_close_paren_is_an_error() {
+        let mut inter = Interpreter::new();
+
+        assert!(matches!(
+            inter.interpret("1 + 2)"),
+            Err(ParseError::ParensMismatch)
+        ))
+    }
+
+    #[test]
+    fn malformed_number_is_an_error() {
+        let mut inter = Interpreter::new();
+
+        assert!(matches!(
+            inter.interpret("1.2.3 + 1"),
+            Err(ParseError::InvalidNumber(_))
+        ))
+    }
+
+    #[test]
+    fn function_without_argument_is_an_error() {
+        let mut inter = Interpreter::new();
+
+        assert!(inter.interpret("(sin)").is_err())
+    }
+
+    #[test]
+    fn diagnose_valid_expression() {
+        let mut inter = Interpreter::new();
+
+        let (tree, diagnostics) = inter.diagnose("2 * (3 + sin 1)");
+
+        assert!(diagnostics.is_empty());
+        let tree = tree.unwrap();
+        assert!(!tree.has_errors());
+        assert_eq!(Span::new(0, 15), tree.span());
+    }
+
+    #[test]
+    fn diagnose_reports_every_error() {
+        let mut inter = Interpreter::new();
+
+        let (tree, diagnostics) = inter.diagnose("(1 + 2 * ) + 3");
+
+        assert!(tree.unwrap().has_errors());
+        assert_eq!(1, diagnostics.len());
+        assert!(matches!(
+            diagnostics[0].error(),
+            ParseError::MissingExpression
+        ));
+        assert_eq!(Span::new(9, 9), diagnostics[0].span());
+
+        let (_, diagnostics) = inter.diagnose("((1 + ) * 2 -");
+        let errors: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.error().message(), diagnostic.span()))
+            .collect();
+
+        assert_eq!(
+            vec![
+                (ParseError::ParensMismatch.message(), Span::new(0, 1)),
+                (ParseError::MissingExpression.message(), Span::new(6, 6)),
+                (ParseError::MissingExpression.message(), Span::new(13, 13)),
+            ],
+            errors
+        );
+    }
+
+    #[test]
+    fn unknown_symbols_are_errors() {
+        let mut inter = Interpreter::new();
+
+        assert!(matches!(
+            inter.interpret("1 + 2 $ * 10"),
+            Err(ParseError::InvalidSymbol('$'))
+        ));
+        assert!(matches!(
+            inter.interpret("3 #"),
+            Err(ParseError::InvalidSymbol('#'))
+        ));
+        assert_eq!(3.0, inter.interpret("1 +\t2\r").unwrap());
+
+        // lexing goes on after the symbol
+        let (tokens, diagnostics) = Lexer::new().lex_all("1 ? 2");
+        assert_eq!(2, tokens.len());
+        assert_eq!(Span::new(2, 3), diagnostics[0].span());
+
+        let (_, diagnostics) = inter.diagnose("2 * @");
+        assert!(diagnostics
+            .iter()
+            .any(|diagnostic| matches!(diagnostic.error(), ParseError::InvalidSymbol('@'))));
+    }
+
+    #[test]
+    fn diagnose_builds_the_tree_interpret_evaluates() {
+        // the tree with every operation in parentheses
+        fn render(expr: &Expr) -> String {
+            match expr.kind() {
+                ExprKind::Number(value) => format!("{value:?}"),
+                ExprKind::Pi => String::from("pi"),
+                ExprKind::Identifier(name) => name.clone(),
+                ExprKind::Call(function, arg) => format!("({function} ({}))", render(arg)),
+                ExprKind::Binary(operator, lhs, rhs) => {
+                    format!("(({}) {operator} ({}))", render(lhs), render(rhs))
+                }
+                ExprKind::Error => panic!("unexpected error node"),
+            }
+        }
+
+        let mut inter = Interpreter::new();
+        inter.set_variable("x", 3.0).unwrap();
+
+        for input in [
+            "1+2*3/4",
+            "(9+16)^0.5",
+            "2^3^2",
+            "8-2-1",
+            "2*3^2-1",
+            "sin 2^2 + 1",
+            "1 - - 2 * 3",
+            "x / 2 / 4 + x ^ 2 * pi",
+            "log (x + 7) * cos x",
+        ] {
+            let (tree, diagnostics) = inter.diagnose(input);
+            assert!(diagnostics.is_empty(), "{input}");
+
+            let rendered = render(&tree.unwrap());
+            assert_eq!(
+                inter.interpret(&rendered).unwrap(),
+                inter.interpret(input).unwrap(),
+                "{input} as {rendered}"
+            );
+        }
+
+        assert_eq!(2.5, inter.interpret("1+2*3/4").unwrap());
+        assert_eq!(5.0, inter.interpret("(9+16)^0.5").unwrap());
+        assert_eq!(512.0, inter.interpret("2^3^2").unwrap());
+        assert!(matches!(
+            inter.interpret("3 4"),
+            Err(ParseError::UnexpectedToken(TokenKind::Number(_)))
+        ));
+    }
+
+    #[test]
+    fn diagnose_recovers_partial_tree() {
+        let mut inter = Interpreter::new();
+
+        let (tree, diagnostics) = inter.diagnose("1 + 4) * 5 ^");
+
+        assert_eq!(2, diagnostics.len());
+        assert!(matches!(diagnostics[0].error(), ParseError::ParensMismatch));
+        assert_eq!(Span::new(5, 6), diagnostics[0].span());
+        assert!(matches!(
+            diagnostics[1].error(),
+            ParseError::MissingExpression
+        ));
+
+        let tree = tree.unwrap();
+        assert!(tree.has_errors());
+        assert!(matches!(
+            tree.kind(),
+            ExprKind::Binary(TokenKind::Plus, _, _)
+        ));
+
+        let (tree, diagnostics) = inter.diagnose("1.2.3");
+
+        assert!(tree.is_none());
+        assert_eq!(1, diagnostics.len());
+        assert!(matches!(
+            diagnostics[0].error(),
+            ParseError::InvalidNumber(_)
+        ));
+    }
+
+    #[test]
+    fn test_variable_calculation() {
+        let mut inter = Interpreter::new();
+        inter.set_variable("x", 3.0).unwrap();
+        inter.set_variable("Rate", 0.5).unwrap();
+
+        assert_eq!(3.0 * 3.0 + 1.0, inter.interpret("x*x+1").unwrap());
+        assert_eq!(0.5 * 2.0, inter.interpret("Rate * 2").unwrap());
+    }
+
+    #[test]
+    fn builtin_names_are_reserved() {
+        let mut inter = Interpreter::new();
+
+        assert!(matches!(
+            inter.set_variable("Sin", 1.0),
+            Err(ParseError::InvalidIdentifier(_))
+        ));
+        assert!(inter.set_variable("x1", 1.0).is_err());
+    }
+
+    #[test]
+    fn unknown_function_suggests_builtin() {
+        let mut inter = Interpreter::new();
+
+        match inter.interpret("sine 1") {
+            Err(ParseError::UnknownIdentifier(name, suggestions)) => {
+                assert_eq!("sine", name);
+                assert_eq!(vec!["sin"], suggestions);
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+
+        match inter.interpret("lg 2") {
+            Err(ParseError::UnknownIdentifier(_, suggestions)) => {
+                assert_eq!(vec!["log"], suggestions)
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn unknown_identifier_suggests_variables() {
+        let mut inter = Interpreter::new();
+        inter.set_variable("radius", 2.0).unwrap();
+
+        let err = inter.interpret("2 * pi * raduis").unwrap_err();
+
+        assert_eq!(
+            "Unknown identifier: `raduis`, did you mean `radius`?",
+            err.message()
+        );
+
+        let err = inter.interpret("qwerty").unwrap_err();
+
+        assert_eq!("Unknown identifier: `qwerty`", err.message());
+    }
+
+    #[test]
+    fn subscriber_receives_events_up_to_level() {
+        let mut inter = Interpreter::new();
+        let events = Arc::new(Mutex::new(Vec::new()));
+
+        let sink = Arc::clone(&events);
+        inter.set_subscriber(Level::Debug, move |event| {
+            sink.lock().unwrap().push((event.level, event.stage))
+        });
+
+        inter.interpret("1 + 2").unwrap();
+
+        let events = events.lock().unwrap();
+        assert!(events.contains(&(Level::Debug, Stage::Lexer)));
+        assert!(events.contains(&(Level::Debug, Stage::Parser)));
+        assert!(events.contains(&(Level::Debug, Stage::Evaluator)));
+        assert!(!events.iter().any(|(level, _)| *level == Level::Trace));
+    }
+
+    #[test]
+    fn cleared_subscriber_is_silent() {
+        let mut inter = Interpreter::new();
+        let events = Arc::new(Mutex::new(0));
+
+        let sink = Arc::clone(&events);
+        inter.set_subscriber(Level::Trace, move |_| *sink.lock().unwrap() += 1);
+        inter.clear_subscriber();
+
+        inter.interpret("1 + 2").unwrap();
+
+        assert_eq!(0, *events.lock().unwrap());
+    }
+
+    #[test]
+    fn test_eval() {
+        assert_eq!(10.0 * (10.0 + 1.0), crate::eval("10 * (10 + 1)").unwrap())
+    }
+
+    #[test]
+    fn compiled_expression_uses_current_variables() {
+        let mut inter = Interpreter::new();
+        let expression = inter.compile("x * 2").unwrap();
+
+        inter.set_variable("x", 1.0).unwrap();
+        assert_eq!(2.0, inter.evaluate(&expression).unwrap());
+
+        inter.set_variable("x", 4.0).unwrap();
+        assert_eq!(8.0, inter.evaluate(&expression).unwrap());
+        assert_eq!("x * 2", expression.source());
+    }
+
+    #[test]
+    fn compiled_expression_evaluates_bindings() {
+        let mut inter = Interpreter::new();
+        let compiled = inter.compile("x * y + x").unwrap();
+
+        assert_eq!(&["x", "y"], compiled.variables());
+        assert_eq!(Some(1), compiled.slot("y"));
+
+        assert_eq!(2.0 * 3.0 + 2.0, compiled.eval(&[2.0, 3.0]).unwrap());
+        assert_eq!(5.0 * 0.5 + 5.0, compiled.eval(&[5.0, 0.5]).unwrap());
+        assert!(matches!(
+            compiled.eval(&[1.0]),
+            Err(ParseError::UnboundVariable(_))
+        ));
+    }
+
+    #[test]
+    fn compiled_expression_keeps_float_policy() {
+        let mut inter = Interpreter::new();
+        inter.set_float_policy(FloatPolicy::Warn);
+        let compiled = inter.compile("log x").unwrap();
+
+        let mut warnings = Vec::new();
+        assert!(compiled
+            .eval_with_warnings(&[0.0], &mut warnings)
+            .unwrap()
+            .is_infinite());
+        assert_eq!(1, warnings.len());
+    }
+
+    #[test]
+    fn compiled_expression_is_shareable() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<CompiledExpr>();
+
+        let compiled = Arc::new(Interpreter::new().compile("2 * x").unwrap());
+
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let compiled = Arc::clone(&compiled);
+                std::thread::spawn(move || compiled.eval(&[i as f32]).unwrap())
+            })
+            .collect();
+
+        for (i, handle) in handles.into_iter().enumerate() {
+            assert_eq!(2.0 * i as f32, handle.join().unwrap());
+        }
+    }
+
+    const FRAGMENTS: &[&str] = &[
+        "0",
+        "1",
+        "2.5",
+        "10",
+        "1.2.3",
+        ".",
+        "+",
+        "-",
+        "*",
+        "/",
+        "^",
+        "(",
+        ")",
+        " ",
+        "sin",
+        "cos",
+        "tan",
+        "log",
+        "pi",
+        "x",
+        "é",
+        "$",
+        "\t",
+        "99999999999",
+    ];
+
+    /// Small xorshift generator so the fuzz corpus is reproducible without extra dependencies.
+    struct Xorshift(u64);
+
+    impl Xorshift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, bound: usize) -> usize {
+            (self.next() % bound as u64) as usize
+        }
+
+        fn input(&mut self) -> String {
+            let len = self.below(12);
+
+            (0..len)
+                .map(|_| FRAGMENTS[self.below(FRAGMENTS.len())])
+                .collect()
+        }
+    }
+
+    #[test]
+    fn fuzz_interpret_never_panics() {
+        for policy in [FloatPolicy::Strict, FloatPolicy::Ieee, FloatPolicy::Warn] {
+            let mut rng = Xorshift(0x2545_f491_4f6c_dd1d);
+            let mut inter = Interpreter::new();
+            inter.set_float_policy(policy);
+
+            for _ in 0..20_000 {
+                let input = rng.input();
+
+                let _ = inter.interpret(&input);
+                let _ = inter.diagnose(&input);
+            }
+        }
+    }
+
+    #[test]
+    fn bytecode_matches_token_walker() {
+        let mut rng = Xorshift(0x9e37_79b9_7f4a_7c15);
+        let mut lexer = Lexer::new();
+        let mut parser = Parser::new();
+        parser.set_float_policy(FloatPolicy::Ieee);
+        parser.registry_mut().set_variable("x", 0.75).unwrap();
+
+        let mut compared = 0;
+
+        for _ in 0..40_000 {
+            let input = rng.input();
+
+            let Ok(queue) = lexer.lex(&input).and_then(|tokens| parser.parse(tokens)) else {
+                continue;
+            };
+            let walked = parser.execute(&queue);
+            let compiled = CompiledExpr::new(&input, queue, FloatPolicy::Ieee)
+                .and_then(|compiled| compiled.eval(&vec![0.75; compiled.variables().len()]));
+
+            if let (Ok(walked), Ok(compiled)) = (walked, compiled) {
+                assert!(
+                    walked.to_bits() == compiled.to_bits() || walked.is_nan() && compiled.is_nan(),
+                    "{input:?}: {walked} != {compiled}"
+                );
+                compared += 1;
+            }
+        }
+
+        assert!(compared > 1_000);
+    }
+
+    #[test]
+    fn bytecode_resolves_slots_and_stack_at_compile_time() {
+        let mut inter = Interpreter::new();
+        let compiled = inter.compile("y - x * 2").unwrap();
+
+        assert_eq!(
+            &[
+                Instruction::Load(0),
+                Instruction::Load(1),
+                Instruction::Const(2.0),
+                Instruction::Mul,
+                Instruction::Sub,
+            ],
+            compiled.program().instructions()
+        );
+        assert_eq!(4.0 - 1.0 * 2.0, compiled.eval(&[4.0, 1.0]).unwrap());
+
+        assert!(matches!(
+            inter.compile("+ 1"),
+            Err(ParseError::MissingExpression)
+        ));
+    }
+
+    #[cfg(feature = "jit")]
+    #[test]
+    fn jit_matches_compiled_evaluation() {
+        // bindings `f32` holds exactly, some large or small enough that
+        // `f32` overflows or underflows where `f64` would not
+        let values = [
+            -0.5f32,
+            0.0,
+            2.0,
+            -3.0,
+            1e6,
+            -1e30,
+            1e20,
+            1e-30,
+            std::f32::consts::FRAC_PI_2,
+        ];
+        let (mut compared, mut failed) = (0, 0);
+
+        for policy in [FloatPolicy::Strict, FloatPolicy::Ieee, FloatPolicy::Warn] {
+            let mut rng = Xorshift(0x2545_f491_4f6c_dd1d);
+            let mut inter = Interpreter::new();
+            inter.set_float_policy(policy);
+
+            for _ in 0..4_000 {
+                let input = rng.input();
+                let Ok(compiled) = inter.compile(&input) else {
+                    continue;
+                };
+                let jit = compiled.jit();
+
+                for value in values {
+                    let narrowed = vec![value; compiled.variables().len()];
+                    let bindings = vec![f64::from(value); narrowed.len()];
+
+                    let (mut expected_warnings, mut warnings) = (Vec::new(), Vec::new());
+                    let expected = compiled.eval_with_warnings(&narrowed, &mut expected_warnings);
+                    let actual = jit.eval_with_warnings(&bindings, &mut warnings);
+
+                    match (expected, actual) {
+                        // `Ieee` keeps the `f64` result even where `f32` is
+                        // no longer finite
+                        (Ok(_), Ok(_)) if policy == FloatPolicy::Ieee => {}
+                        (Ok(expected), Ok(actual)) => {
+                            let expected = f64::from(expected);
+                            assert!(
+                                expected == actual
+                                    || (expected - actual).abs() <= 1e-3 * expected.abs().max(1.0)
+                                    || expected.is_nan() && actual.is_nan(),
+                                "{input:?} at {value}: {expected} != {actual}"
+                            );
+                        }
+                        (Err(expected), Err(actual)) => {
+                            assert_eq!(expected.message(), actual.message(), "{input:?}");
+                            failed += 1;
+                        }
+                        (expected, actual) => {
+                            panic!("{input:?} at {value}: {expected:?} != {actual:?}")
+                        }
+                    }
+
+                    let messages = |warnings: &[ParseError]| -> Vec<String> {
+                        warnings.iter().map(ParseError::message).collect()
+                    };
+                    assert_eq!(messages(&expected_warnings), messages(&warnings));
+                    compared += 1;
+                }
+            }
+        }
+
+        assert!(compared > 2_000 && failed > 30, "{compared} {failed}");
+    }
+
+    #[cfg(feature = "jit")]
+    #[test]
+    fn jit_evaluates_bindings() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<crate::JitExpr>();
+
+        let mut inter = Interpreter::new();
+        let jit = inter.compile("2 * pi * r + x / 4").unwrap().jit();
+
+        #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+        assert!(jit.is_native());
+
+        let expected = 2.0 * f64::from(PI) * 3.0 + 8.0 / 4.0;
+        assert_eq!(expected, jit.eval(&[3.0, 8.0]).unwrap());
+        assert!(matches!(
+            jit.eval(&[3.0]),
+            Err(ParseError::UnboundVariable(name)) if name == "x"
+        ));
+
+        let jit = inter.compile("1 / r").unwrap().jit();
+        assert!(matches!(jit.eval(&[0.0]), Err(ParseError::DivisionByZero)));
+        // zero once narrowed to `f32`, as the interpreter sees it
+        assert!(matches!(
+            jit.eval(&[1e-300]),
+            Err(ParseError::DivisionByZero)
+        ));
+
+        // beyond `f32`, so an error as in the interpreter
+        for (source, value) in [("r * r", 1e20f32), ("r ^ 40", 10.0)] {
+            let compiled = inter.compile(source).unwrap();
+            assert!(matches!(
+                compiled.eval(&[value]),
+                Err(ParseError::NonFinite(_))
+            ));
+            assert!(matches!(
+                compiled.jit().eval(&[f64::from(value)]),
+                Err(ParseError::NonFinite(_))
+            ));
+        }
+        // a binding too large for `f32` overflows as well
+        let jit = inter.compile("r * r").unwrap().jit();
+        assert!(matches!(jit.eval(&[1e300]), Err(ParseError::NonFinite(_))));
+        let jit = inter.compile("log r").unwrap().jit();
+        assert!(matches!(
+            jit.eval(&[1e-300]),
+            Err(ParseError::OutOfDomain(_))
+        ));
+        assert!(jit.eval(&[-1.0]).is_err());
+
+        inter.set_float_policy(FloatPolicy::Warn);
+        let mut warnings = vec![];
+        let jit = inter.compile("r * r").unwrap().jit();
+        let value = jit.eval_with_warnings(&[1e300], &mut warnings).unwrap();
+        assert!(value.is_infinite() && warnings.len() == 1);
+
+        // `Ieee` keeps the `f64` results
+        inter.set_float_policy(FloatPolicy::Ieee);
+        let jit = inter.compile("r ^ 40").unwrap().jit();
+        assert_eq!(10f64.powf(40.0), jit.eval(&[10.0]).unwrap());
+        let jit = inter.compile("2 * pi * r").unwrap().jit();
+        assert_eq!(2.0 * f64::from(PI) * -1e300, jit.eval(&[-1e300]).unwrap());
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn bigint_backend_is_exact() {
+        use crate::BigInt;
+
+        let mut inter = Interpreter::new();
+        let big = |inter: &mut Interpreter, input: &str| {
+            inter
+                .interpret_as::<BigInt>(input)
+                .map(|value| value.to_string())
+        };
+
+        assert_eq!(
+            "1267650600228229401496703205376",
+            big(&mut inter, "2 ^ 100").unwrap()
+        );
+        assert_eq!(
+            "123456789012345678901234567891",
+            big(&mut inter, "123456789012345678901234567890 + 1").unwrap()
+        );
+        assert_eq!("4", big(&mut inter, "8 / 2").unwrap());
+        assert!(matches!(
+            big(&mut inter, "7 / 2"),
+            Err(ParseError::Unsupported(_))
+        ));
+        assert!(matches!(
+            big(&mut inter, "1 / 0"),
+            Err(ParseError::DivisionByZero)
+        ));
+        assert!(matches!(
+            big(&mut inter, "sin 1"),
+            Err(ParseError::Unsupported(_))
+        ));
+        assert!(matches!(
+            big(&mut inter, "2 * pi"),
+            Err(ParseError::Unsupported(_))
+        ));
+        assert!(matches!(
+            big(&mut inter, "2.5"),
+            Err(ParseError::InvalidNumber(_))
+        ));
+
+        inter.run("x = 3; f(a) = a * 2").unwrap();
+        assert_eq!("18", big(&mut inter, "f(x * x)").unwrap());
+        inter.interpret("x = 0.5").unwrap();
+        assert!(matches!(
+            big(&mut inter, "x"),
+            Err(ParseError::Unsupported(_))
+        ));
+
+        let chain = format!("1{}", " + 1".repeat(100_000));
+        assert_eq!("100001", big(&mut inter, &chain).unwrap());
+
+        inter.set_limits(Limits {
+            max_steps: Some(4),
+            ..Limits::default()
+        });
+        assert!(matches!(
+            big(&mut inter, "1 + 2 + 3"),
+            Err(ParseError::LimitExceeded(Limit::Steps(4)))
+        ));
+    }
+
+    #[cfg(feature = "decimal")]
+    #[test]
+    fn decimal_backend_keeps_decimal_fractions() {
+        use crate::Decimal;
+
+        let mut inter = Interpreter::new();
+        let decimal = |inter: &mut Interpreter, input: &str| inter.interpret_as::<Decimal>(input);
+
+        assert_eq!("0.3", decimal(&mut inter, "0.1 + 0.2").unwrap().to_string());
+        assert_eq!(
+            "0.3333333333333333333333333333",
+            decimal(&mut inter, "1 / 3").unwrap().to_string()
+        );
+        assert_eq!("1024", decimal(&mut inter, "2 ^ 10").unwrap().to_string());
+        assert_eq!(
+            "2",
+            decimal(&mut inter, "log 100")
+                .unwrap()
+                .normalize()
+                .to_string()
+        );
+        assert!(matches!(
+            decimal(&mut inter, "1 / 0"),
+            Err(ParseError::DivisionByZero)
+        ));
+        assert!(matches!(
+            decimal(&mut inter, "log 0"),
+            Err(ParseError::OutOfDomain(_))
+        ));
+        assert!(matches!(
+            decimal(&mut inter, "(0 - 8) ^ 0.5"),
+            Err(ParseError::OutOfDomain(_))
+        ));
+
+        inter.interpret("rate = 0.07").unwrap();
+        assert_eq!(
+            "107.0000",
+            decimal(&mut inter, "100.00 * (1 + rate)")
+                .unwrap()
+                .to_string()
+        );
+    }
+
+    #[cfg(feature = "complex")]
+    #[test]
+    fn complex_backend_has_an_imaginary_unit() {
+        use crate::Complex64;
+
+        let mut inter = Interpreter::new();
+        let complex = |inter: &mut Interpreter, input: &str| inter.interpret_as::<Complex64>(input);
+
+        assert_eq!(
+            Complex64::new(-1.0, 0.0),
+            complex(&mut inter, "i ^ 2").unwrap()
+        );
+        assert_eq!(
+            Complex64::new(2.0, 0.0),
+            complex(&mut inter, "(1 + i) * (1 - i)").unwrap()
+        );
+
+        let log = complex(&mut inter, "log (0 - 100)").unwrap();
+        assert!((log.re - 2.0).abs() < 1e-12);
+        assert!((log.im - std::f64::consts::PI / std::f64::consts::LN_10).abs() < 1e-12);
+
+        assert!(matches!(
+            complex(&mut inter, "1 / 0"),
+            Err(ParseError::DivisionByZero)
+        ));
+        assert!(matches!(
+            complex(&mut inter, "log 0"),
+            Err(ParseError::OutOfDomain(_))
+        ));
+
+        inter.set_angle_unit(AngleUnit::Degrees);
+        assert!((complex(&mut inter, "sin 90").unwrap() - 1.0).norm() < 1e-12);
+    }
+
+    #[cfg(feature = "units")]
+    #[test]
+    fn units_backend_checks_dimensions() {
+        use crate::Quantity;
+
+        let mut inter = Interpreter::new();
+        let quantity = |inter: &mut Interpreter, input: &str| inter.interpret_as::<Quantity>(input);
+
+        let force = quantity(&mut inter, "9.81 * kg * m / s ^ 2").unwrap();
+        assert_eq!(Some(9.81), force.value_in("N"));
+        assert_eq!(None, force.value_in("J"));
+        assert_eq!("9.81 m kg s^-2", force.to_string());
+
+        let distance = quantity(&mut inter, "1500 * m").unwrap();
+        assert_eq!(Some(1.5), distance.value_in("km"));
+        let ratio = quantity(&mut inter, "3 * h / min").unwrap();
+        assert!(ratio.is_dimensionless());
+        assert_eq!(180.0, ratio.value());
+        assert_eq!(
+            "4.0 m^2",
+            quantity(&mut inter, "(2 * m) ^ 2").unwrap().to_string()
+        );
+
+        for input in ["2 * m + 3 * s", "sin (2 * m)", "(2 * m) ^ 0.5", "2 ^ m"] {
+            assert!(
+                matches!(
+                    quantity(&mut inter, input),
+                    Err(ParseError::IncompatibleUnits(_))
+                ),
+                "{input}"
+            );
+        }
+        assert!(matches!(
+            quantity(&mut inter, "metre"),
+            Err(ParseError::UnknownIdentifier(..))
+        ));
+
+        // a variable of the interpreter comes first
+        inter.interpret("m = 2").unwrap();
+        assert!(quantity(&mut inter, "3 * m").unwrap().is_dimensionless());
+    }
+
+    #[test]
+    fn batch_matches_row_by_row() {
+        let mut rng = Xorshift(0x6a09_e667_f3bc_c908);
+        let column = |rng: &mut Xorshift| -> Vec<f32> {
+            (0..1_000)
+                .map(|_| rng.below(4_000) as f32 / 100.0 - 20.0)
+                .collect()
+        };
+        let (x, r) = (column(&mut rng), column(&mut rng));
+
+        for policy in [FloatPolicy::Ieee, FloatPolicy::Warn] {
+            let mut inter = Interpreter::new();
+            inter.set_float_policy(policy);
+
+            let compiled = inter.compile("x ^ 2 / r - log (r * r) + tan x").unwrap();
+            let mut out = vec![0.0; x.len()];
+            compiled.eval_batch(&[&x, &r], &mut out).unwrap();
+
+            for (row, value) in out.iter().enumerate() {
+                let expected = compiled.eval(&[x[row], r[row]]).unwrap();
+                assert!(
+                    expected.to_bits() == value.to_bits() || expected.is_nan() && value.is_nan()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn batch_reports_row_errors() {
+        let mut inter = Interpreter::new();
+        let compiled = inter.compile("1 / (x - y)").unwrap();
+        let x: Vec<f32> = (0..600).map(|row| row as f32).collect();
+        let mut y = vec![-1.0; 600];
+        y[300] = 300.0;
+        let mut out = vec![0.0; 600];
+
+        assert!(matches!(
+            compiled.eval_batch(&[&x, &y], &mut out),
+            Err(ParseError::DivisionByZero)
+        ));
+        assert_eq!(1.0 / 300.0_f32, out[299]);
+
+        assert!(matches!(
+            compiled.eval_batch(&[&x], &mut out),
+            Err(ParseError::UnboundVariable(name)) if name == "y"
+        ));
+        assert!(matches!(
+            compiled.eval_batch(&[&x, &y[..10]], &mut out),
+            Err(ParseError::ColumnLength(name, 10, 600)) if name == "y"
+        ));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn parallel_batch_matches_serial() {
+        let mut inter = Interpreter::new();
+        let compiled = inter.compile("x ^ 2 / (x - 3000)").unwrap();
+        let x: Vec<f32> = (0..5_000).map(|row| row as f32).collect();
+
+        let (mut serial, mut parallel) = (vec![0.0; x.len()], vec![0.0; x.len()]);
+        compiled
+            .eval_batch(&[&x[..2_000]], &mut serial[..2_000])
+            .unwrap();
+        compiled
+            .eval_batch_parallel(&[&x[..2_000]], &mut parallel[..2_000], 3)
+            .unwrap();
+        assert_eq!(serial, parallel);
+
+        assert!(matches!(
+            compiled.eval_batch_parallel(&[&x], &mut parallel, 4),
+            Err(ParseError::DivisionByZero)
+        ));
+        assert_eq!(2_999.0_f32.powf(2.0) / -1.0, parallel[2_999]);
+    }
+
+    #[test]
+    fn optimizer_folds_and_simplifies() {
+        let mut inter = Interpreter::new();
+
+        let instructions = |inter: &mut Interpreter, expression: &str| {
+            inter
+                .compile(expression)
+                .unwrap()
+                .program()
+                .instructions()
+                .to_vec()
+        };
+
+        assert_eq!(
+            vec![
+                Instruction::Const(2.0 * PI),
+                Instruction::Load(0),
+                Instruction::Mul
+            ],
+            instructions(&mut inter, "2 * pi * r")
+        );
+        assert_eq!(
+            vec![Instruction::Load(0)],
+            instructions(&mut inter, "x * 1 + 0 - 0")
+        );
+        assert_eq!(
+            vec![Instruction::Load(0)],
+            instructions(&mut inter, "x ^ 1")
+        );
+        assert_eq!(
+            vec![
+                Instruction::Load(0),
+                Instruction::Sin,
+                Instruction::Save(0),
+                Instruction::Recall(0),
+                Instruction::Mul,
+                Instruction::Recall(0),
+                Instruction::Add,
+            ],
+            instructions(&mut inter, "sin x * sin x + sin x")
+        );
+
+        inter.set_optimize(false);
+        assert_eq!(5, instructions(&mut inter, "x * 1 + 0").len());
+    }
+
+    #[test]
+    fn optimizer_keeps_errors_and_warnings() {
+        let mut inter = Interpreter::new();
+
+        let compiled = inter.compile("1 / 0 + x").unwrap();
+        assert!(matches!(
+            compiled.eval(&[1.0]),
+            Err(ParseError::DivisionByZero)
+        ));
+        assert!(matches!(
+            inter.interpret("log 0 * 2"),
+            Err(ParseError::OutOfDomain(_))
+        ));
+
+        inter.set_float_policy(FloatPolicy::Warn);
+        let compiled = inter.compile("log x + log x").unwrap();
+        let mut warnings = Vec::new();
+        compiled.eval_with_warnings(&[0.0], &mut warnings).unwrap();
+        assert_eq!(2, warnings.len());
+    }
+
+    #[test]
+    fn optimized_matches_unoptimized() {
+        for policy in [FloatPolicy::Strict, FloatPolicy::Ieee, FloatPolicy::Warn] {
+            let mut rng = Xorshift(0xbb67_ae85_84ca_a73b);
+            let mut inter = Interpreter::new();
+            inter.set_float_policy(policy);
+
+            for _ in 0..10_000 {
+                let input = rng.input();
+
+                inter.set_optimize(false);
+                let Ok(plain) = inter.compile(&input) else {
+                    continue;
+                };
+                inter.set_optimize(true);
+                let optimized = inter.compile(&input).unwrap();
+                let bindings = vec![-1.5; plain.variables().len()];
+
+                let (mut expected_warnings, mut warnings) = (Vec::new(), Vec::new());
+                let expected = plain.eval_with_warnings(&bindings, &mut expected_warnings);
+                let actual = optimized.eval_with_warnings(&bindings, &mut warnings);
+
+                match (expected, actual) {
+                    // zeros may differ in sign where `x + 0` was dropped
+                    (Ok(expected), Ok(actual)) => assert!(
+                        expected == actual || expected.is_nan() && actual.is_nan(),
+                        "{input:?}: {expected} != {actual}"
+                    ),
+                    (Err(expected), Err(actual)) => {
+                        assert_eq!(expected.message(), actual.message(), "{input:?}")
+                    }
+                    (expected, actual) => panic!("{input:?}: {expected:?} != {actual:?}"),
+                }
+                assert_eq!(expected_warnings.len(), warnings.len(), "{input:?}");
+
+                let column = vec![-1.5; 300];
+                let columns = vec![column.as_slice(); optimized.variables().len()];
+                let mut out = vec![0.0; column.len()];
+                if optimized.eval_batch(&columns, &mut out).is_ok() {
+                    let expected = optimized.eval(&bindings).unwrap();
+                    assert!(out.iter().all(|value| value.to_bits() == expected.to_bits()
+                        || value.is_nan() && expected.is_nan()));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cache_reuses_compiled_expressions() {
+        let mut inter = Interpreter::new();
+        inter.interpret("1 + 1").unwrap();
+        assert_eq!(CacheStats::default(), inter.cache_stats());
+
+        inter.set_cache_capacity(2);
+        inter.set_variable("x", 2.0).unwrap();
+        assert_eq!(4.0, inter.interpret("x * 2").unwrap());
+        inter.set_variable("x", 3.0).unwrap();
+        assert_eq!(6.0, inter.interpret("  x  *\t2 ").unwrap());
+        assert!(inter.interpret("x *").is_err());
+
+        let stats = inter.cache_stats();
+        assert_eq!((1, 2, 1), (stats.hits, stats.misses, stats.len));
+
+        inter.interpret("1").unwrap();
+        inter.interpret("x * 2").unwrap();
+        inter.interpret("2").unwrap();
+        let stats = inter.cache_stats();
+        assert_eq!((2, 1, 2), (stats.hits, stats.evictions, stats.len));
+
+        // "1" was least recently used, so it was the one evicted
+        inter.interpret("x * 2").unwrap();
+        inter.interpret("1").unwrap();
+        assert_eq!(3, inter.cache_stats().hits);
+    }
+
+    #[test]
+    fn cache_is_invalidated() {
+        let mut inter = Interpreter::new();
+        inter.set_cache_capacity(8);
+        inter.set_variable("x", 0.0).unwrap();
+        inter.interpret("1 / x + 1").unwrap_err();
+        inter.interpret("2 * 3").unwrap();
+
+        inter.set_float_policy(FloatPolicy::Ieee);
+        assert_eq!(0, inter.cache_stats().len);
+        assert_eq!(f32::INFINITY, inter.interpret("1 / x + 1").unwrap());
+        inter.interpret("2 * 3").unwrap();
+
+        assert_eq!(1, inter.invalidate_cached("x"));
+        assert_eq!(1, inter.cache_stats().len);
+
+        inter.set_optimize(false);
+        assert_eq!(0, inter.cache_stats().len);
+    }
+
+    #[test]
+    fn cache_keeps_line_breaks_apart() {
+        let mut inter = Interpreter::new();
+        inter.set_cache_capacity(10);
+        inter.set_variable("x", 5.0).unwrap();
+
+        assert_eq!(Some(4.0), inter.run("x - 1").unwrap());
+        // two statements, the second of them incomplete, not the cached `x - 1`
+        assert!(inter.run("x\n- 1").is_err());
+        assert_eq!(0, inter.cache_stats().hits);
+
+        assert_eq!(Some(4.0), inter.run("  x -\t1 \n").unwrap());
+        assert_eq!(1, inter.cache_stats().hits);
+    }
+
+    #[test]
+    fn limits_are_reported_distinctly() {
+        let limited = |limits: Limits| {
+            let mut inter = Interpreter::new();
+            inter.set_limits(limits);
+            inter
+        };
+
+        let mut inter_length = limited(Limits {
+            max_input_length: Some(5),
+            ..Limits::default()
+        });
+        assert!(matches!(
+            inter_length.interpret("1 + 2 + 3"),
+            Err(ParseError::LimitExceeded(Limit::InputLength(5)))
+        ));
+        assert_eq!(3.0, inter_length.interpret("1 + 2").unwrap());
+
+        let mut inter_tokens = limited(Limits {
+            max_tokens: Some(3),
+            ..Limits::default()
+        });
+        assert!(matches!(
+            inter_tokens.interpret("1 + 2 + 3"),
+            Err(ParseError::LimitExceeded(Limit::Tokens(3)))
+        ));
+        let (_, diagnostics) = inter_tokens.diagnose("1 + 2 + 3");
+        assert_eq!(Span::new(6, 7), diagnostics[0].span());
+
+        let mut inter_depth = limited(Limits {
+            max_depth: Some(2),
+            ..Limits::default()
+        });
+        assert_eq!(1.0, inter_depth.interpret("((1))").unwrap());
+        assert!(matches!(
+            inter_depth.interpret("(((1)))"),
+            Err(ParseError::LimitExceeded(Limit::Depth(2)))
+        ));
+        let (_, diagnostics) = inter_depth.diagnose("(((1)))");
+        assert!(matches!(
+            diagnostics[0].error(),
+            ParseError::LimitExceeded(Limit::Depth(2))
+        ));
+        // without limits, only the parentheses themselves are checked
+        let (_, diagnostics) =
+            Interpreter::new().diagnose(&format!("{}1{}", "(".repeat(300), ")".repeat(300)));
+        assert!(diagnostics.is_empty());
+
+        let mut inter_steps = limited(Limits {
+            max_steps: Some(4),
+            ..Limits::default()
+        });
+        inter_steps.set_variable("x", 1.0).unwrap();
+        assert!(matches!(
+            inter_steps.interpret("x * x * x"),
+            Err(ParseError::LimitExceeded(Limit::Steps(4)))
+        ));
+        // folded down to a single constant
+        assert_eq!(8.0, inter_steps.interpret("2 * 2 * 2").unwrap());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn timeout_is_checked_between_stages() {
+        let timeout = std::time::Duration::from_nanos(1);
+        let mut inter = Interpreter::new();
+        let long = vec!["1"; 10_000].join(" + ");
+        assert_eq!(10_000.0, inter.interpret(&long).unwrap());
+
+        inter.set_limits(Limits {
+            timeout: Some(timeout),
+            ..Limits::default()
+        });
+        assert!(matches!(
+            inter.interpret(&long),
+            Err(ParseError::LimitExceeded(Limit::Time(limit))) if limit == timeout
+        ));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn timeout_is_checked_while_evaluating() {
+        use crate::ast::{
+            evaluator::Evaluation,
+            limits::{Budget, Deadline},
+            trace::Tracer,
+        };
+
+        let mut inter = Interpreter::new();
+        let sum = inter.compile(&vec!["x"; 1_000].join(" + ")).unwrap();
+        let mut warnings = vec![];
+        let tracer = Tracer::default();
+        let deadline = Deadline::start(&Limits {
+            timeout: Some(std::time::Duration::ZERO),
+            ..Limits::default()
+        });
+        std::thread::sleep(std::time::Duration::from_millis(1));
+
+        let mut evaluation = Evaluation {
+            float_policy: FloatPolicy::Ieee,
+            warnings: &mut warnings,
+            tracer: &tracer,
+            budget: Budget {
+                max_steps: None,
+                deadline: Some(&deadline),
+            },
+        };
+        assert!(matches!(
+            sum.program().run(&[1.0], &mut evaluation),
+            Err(ParseError::LimitExceeded(Limit::Time(_)))
+        ));
+        evaluation.budget.deadline = None;
+        assert_eq!(1_000.0, sum.program().run(&[1.0], &mut evaluation).unwrap());
+    }
+
+    #[test]
+    fn deep_input_does_not_overflow() {
+        let mut inter = Interpreter::new();
+        inter.set_variable("x", 1.0).unwrap();
+
+        let chain = format!("x{}", " + 1".repeat(100_000));
+        assert_eq!(100_001.0, inter.interpret(&chain).unwrap());
+        let (tree, diagnostics) = inter.diagnose(&chain);
+        assert!(tree.is_some() && diagnostics.is_empty());
+
+        let nested = format!("{}1{}", "(".repeat(100_000), ")".repeat(100_000));
+        assert_eq!(1.0, inter.interpret(&nested).unwrap());
+        let (tree, diagnostics) = inter.diagnose(&nested);
+        assert_eq!(ExprKind::Number(1.0), *tree.unwrap().kind());
+        assert!(diagnostics.is_empty());
+
+        let calls = format!("{}x", "sin ".repeat(100_000));
+        assert!(inter.interpret(&calls).is_ok());
+        let (_, diagnostics) = inter.diagnose(&calls);
+        assert!(diagnostics.is_empty());
+
+        let powers = format!("{}1", "1 ^ ".repeat(100_000));
+        assert_eq!(1.0, inter.interpret(&powers).unwrap());
+    }
+
+    #[cfg(feature = "repl")]
+    #[test]
+    fn run_lines_prints_one_result_per_line() {
+        let mut inter = Interpreter::new();
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+
+        let succeeded = inter
+            .run_lines(
+                "1 + 1\n\n2 * 3\nquit\n4".as_bytes(),
+                &mut output,
+                &mut errors,
+            )
+            .unwrap();
+
+        assert!(succeeded);
+        assert_eq!("2.0\n6.0\n", String::from_utf8(output).unwrap());
+        assert!(errors.is_empty());
+
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+
+        let succeeded = inter
+            .run_lines("1 / 0\n2".as_bytes(), &mut output, &mut errors)
+            .unwrap();
+
+        assert!(!succeeded);
+        assert_eq!("2.0\n", String::from_utf8(output).unwrap());
+        assert!(String::from_utf8(errors).unwrap().starts_with("Error: "));
+    }
+
+    #[test]
+    fn registry_completes_names() {
+        let mut inter = Interpreter::new();
+        inter.set_variable("scale", 2.0).unwrap();
+        inter.set_variable("Size", 3.0).unwrap();
+
+        assert_eq!(vec!["scale", "sin"], inter.registry().complete("s"));
+        assert_eq!(vec!["sin"], inter.registry().complete("SI"));
+        assert_eq!(vec!["Size", "sin"], inter.registry().complete("Si"));
+        assert!(inter.registry().complete("x").is_empty());
+
+        assert_eq!("sin x", crate::describe("SIN").unwrap().0);
+        assert!(crate::describe("scale").is_none());
+    }
+
+    #[test]
+    fn preview_leaves_the_interpreter_alone() {
+        let mut inter = Interpreter::new();
+        inter.set_variable("x", 2.0).unwrap();
+        inter.set_cache_capacity(4);
+        inter.set_float_policy(FloatPolicy::Warn);
+
+        assert_eq!(Some(5.0), inter.preview("x * 2 + 1").unwrap());
+        assert!(inter.preview("log 0").unwrap().unwrap().is_infinite());
+        assert!(inter.warnings().is_empty());
+
+        assert_eq!(
+            Some(9.0),
+            inter.preview("y = 4; f(a) = a * 2; f(y) + 1").unwrap()
+        );
+        assert!(inter.variable("y").is_none());
+        assert!(inter.function("f").is_none());
+        assert_eq!(0, inter.cache_stats().misses);
+        assert_eq!(0, inter.cache_stats().len);
+
+        assert!(matches!(
+            inter.preview("y + 1"),
+            Err(ParseError::UnknownIdentifier(..))
+        ));
+    }
+
+    #[test]
+    fn preview_runs_within_tight_limits() {
+        let mut inter = Interpreter::new();
+        let long = vec!["1"; 200].join(" + ");
+        assert_eq!(200.0, inter.interpret(&long).unwrap());
+        assert!(matches!(
+            inter.preview(&long),
+            Err(ParseError::LimitExceeded(Limit::InputLength(256)))
+        ));
+        assert!(matches!(
+            inter.preview(&vec!["1"; 100].join("+")),
+            Err(ParseError::LimitExceeded(Limit::Tokens(128)))
+        ));
+
+        inter.set_limits(Limits {
+            max_tokens: Some(3),
+            ..Limits::default()
+        });
+        assert!(matches!(
+            inter.preview("1 + 2 + 3"),
+            Err(ParseError::LimitExceeded(Limit::Tokens(3)))
+        ));
+        assert_eq!(Some(3.0), inter.preview("1 + 2").unwrap());
+    }
+
+    #[test]
+    fn angle_unit_converts_trigonometric_arguments() {
+        let mut inter = Interpreter::new();
+        inter.set_angle_unit(AngleUnit::Degrees);
+
+        assert_eq!(1.0, inter.interpret("sin 90").unwrap());
+        assert!((inter.interpret("cos 120").unwrap() + 0.5).abs() < 1e-6);
+        assert_eq!(2.0, inter.interpret("log 100").unwrap());
+
+        inter.reset();
+
+        assert_eq!(AngleUnit::Radians, inter.angle_unit());
+        assert!(inter.interpret("sin pi").unwrap().abs() < 1e-6);
+    }
+
+    #[cfg(feature = "repl")]
+    #[test]
+    fn console_commands_run_in_piped_input() {
+        let mut inter = Interpreter::new();
+        inter.set_variable("x", 2.0).unwrap();
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+
+        let input = ":vars\n:mode deg warn\nsin 90\n:reset\n:vars\n:tokens 1\n:nope";
+        let succeeded = inter
+            .run_lines(input.as_bytes(), &mut output, &mut errors)
+            .unwrap();
+
+        assert!(!succeeded);
+        assert_eq!(
+            "x            = 2.0\n\
+             float policy: warn, angle unit: deg\n\
+             1.0\n\
+             No variables defined\n\
+             Number(1.0)  0..1\n",
+            String::from_utf8(output).unwrap()
+        );
+        assert_eq!(
+            "Error: Unknown command: `:nope`\n",
+            String::from_utf8(errors).unwrap()
+        );
+    }
+
+    #[test]
+    fn recorded_results_are_referenced_as_ans_and_dollar() {
+        let mut inter = Interpreter::new();
+
+        assert!(matches!(
+            inter.interpret("ans"),
+            Err(ParseError::UnknownIdentifier(..))
+        ));
+
+        assert_eq!(1, inter.record_result(2.0));
+        assert_eq!(2, inter.record_result(5.0));
+
+        assert_eq!(10.0, inter.interpret("ans * 2").unwrap());
+        assert_eq!(3.0, inter.interpret("$2 - $1").unwrap());
+        assert_eq!(vec![2.0, 5.0], inter.results());
+        assert!(matches!(
+            inter.interpret("$3"),
+            Err(ParseError::UnknownIdentifier(..))
+        ));
+        assert!(matches!(
+            inter.interpret("$0"),
+            Err(ParseError::UnknownIdentifier(..))
+        ));
+
+        inter.reset();
+        assert!(inter.results().is_empty());
+        assert!(inter.variable("ans").is_none());
+    }
+
+    #[test]
+    fn statements_run_in_sequence() {
+        let mut inter = Interpreter::new();
+
+        assert_eq!(7.0, inter.interpret("a = 3; b = a + 1; a + b").unwrap());
+        assert_eq!(Some(4.0), inter.variable("b"));
+        assert_eq!(12.0, inter.interpret("a * b\n").unwrap());
+        assert_eq!(5.0, inter.interpret("c = (1 +\n 4)\nc").unwrap());
+        assert_eq!(Some(2.0), inter.run("a = 2").unwrap());
+
+        assert!(matches!(
+            inter.interpret("; \n"),
+            Err(ParseError::ExpressionEmpty)
+        ));
+        assert!(matches!(
+            inter.interpret("sin = 2"),
+            Err(ParseError::InvalidIdentifier(name)) if name == "sin"
+        ));
+        assert!(matches!(
+            inter.interpret("1 + = 2"),
+            Err(ParseError::InvalidIdentifier(..))
+        ));
+        assert!(matches!(
+            inter.interpret("a = "),
+            Err(ParseError::MissingExpression)
+        ));
+        assert!(matches!(
+            inter.compile("a = 1"),
+            Err(ParseError::UnexpectedToken(TokenKind::Equals))
+        ));
+
+        // the statements before a failing one have run
+        assert!(inter.interpret("d = 1; e + 1").is_err());
+        assert_eq!(Some(1.0), inter.variable("d"));
+    }
+
+    #[test]
+    fn user_functions_expand_at_the_call() {
+        let mut inter = Interpreter::new();
+        inter.set_cache_capacity(4);
+
+        assert_eq!(None, inter.run("sq(x) = x * x").unwrap());
+        assert!(matches!(
+            inter.interpret("id(x) = x"),
+            Err(ParseError::NoValue)
+        ));
+        assert_eq!(9.0, inter.interpret("sq(1 + 2)").unwrap());
+
+        inter.run("hyp(a, b) = sq(a) + sq(b)").unwrap();
+        assert_eq!(25.0, inter.interpret("hyp(3, sq(2))").unwrap());
+        assert_eq!(
+            "hyp(a, b) = sq(a) + sq(b)",
+            inter.function("hyp").unwrap().to_string()
+        );
+
+        // redefining drops cached expressions that used the old body
+        inter.run("sq(x) = x * x * x").unwrap();
+        assert_eq!(27.0, inter.interpret("sq(1 + 2)").unwrap());
+
+        assert!(matches!(
+            inter.interpret("sq(1, 2)"),
+            Err(ParseError::ArgumentCount(name, 1, 2)) if name == "sq"
+        ));
+        assert!(matches!(
+            inter.interpret("loop(x) = loop(x) + 1"),
+            Err(ParseError::RecursiveFunction(..))
+        ));
+        assert!(matches!(
+            inter.interpret("g(x, x) = x"),
+            Err(ParseError::InvalidIdentifier(name)) if name == "x"
+        ));
+        assert!(matches!(
+            inter.interpret("g(x) = x +"),
+            Err(ParseError::MissingExpression)
+        ));
+        assert!(inter.function("g").is_none());
+
+        inter.set_limits(Limits {
+            max_call_depth: Some(1),
+            ..Limits::default()
+        });
+        assert!(matches!(
+            inter.interpret("sq(sq(2))"),
+            Err(ParseError::LimitExceeded(Limit::CallDepth(1)))
+        ));
+
+        assert!(inter.remove_function("sq").is_some());
+        assert!(inter.interpret("sq(2)").is_err());
+    }
+
+    #[test]
+    fn nested_calls_stop_expanding_at_the_token_limit() {
+        let source = format!("g(x) = {}; g(g(g(g(g(1)))))", vec!["x"; 60].join("+"));
+        assert_eq!(144, source.len());
+
+        let mut inter = Interpreter::new();
+        inter.set_limits(Limits {
+            max_tokens: Some(1_000),
+            max_call_depth: Some(8),
+            #[cfg(feature = "std")]
+            timeout: Some(std::time::Duration::from_millis(100)),
+            ..Limits::default()
+        });
+        assert!(matches!(
+            inter.run(&source),
+            Err(ParseError::LimitExceeded(Limit::Tokens(1_000)))
+        ));
+        assert!(matches!(
+            inter.preview("g(g(g(g(g(1)))))"),
+            Err(ParseError::LimitExceeded(Limit::Tokens(128)))
+        ));
+        let (tree, diagnostics) = inter.diagnose("g(g(g(g(g(1)))))");
+        assert!(tree.is_none());
+        assert!(matches!(
+            diagnostics[0].error(),
+            ParseError::LimitExceeded(Limit::Tokens(1_000))
+        ));
+        assert_eq!(60.0, inter.interpret("g(1)").unwrap());
+    }
+
+    #[cfg(feature = "repl")]
+    #[test]
+    fn run_lines_continues_open_parentheses() {
+        let mut inter = Interpreter::new();
+        let input = "f(x) = (x +\n  1)\nf(2); f(3)\n:funcs\n(1 +\n";
+        let (mut output, mut errors) = (Vec::new(), Vec::new());
+
+        assert!(!inter
+            .run_lines(input.as_bytes(), &mut output, &mut errors)
+            .unwrap());
+        assert_eq!(
+            "4.0\nsin x        Sine of the angle x\ncos x        Cosine of the angle x\n\
+             tan x        Tangent of the angle x\nlog x        Base 10 logarithm of x, for x > 0\n\
+             f(x) = (x + 1)\n",
+            String::from_utf8(output).unwrap()
+        );
+        assert_eq!(
+            "Error: Expression is incomplete!\n",
+            String::from_utf8(errors).unwrap()
+        );
+    }
+
+    #[test]
+    fn sessions_are_saved_and_loaded() {
+        let mut inter = Interpreter::new();
+        inter.set_float_policy(FloatPolicy::Ieee);
+        inter.set_angle_unit(AngleUnit::Degrees);
+        inter.run("b(x) = x + 1; a(x) = b(x) * 2; r = 0.1").unwrap();
+        inter.set_variable("big", f32::INFINITY).unwrap();
+        inter.record_result(1e20);
+
+        // `a` calls `b`, so `b` has to come first
+        let session = inter.save_session();
+        assert_eq!(
+            "# arth_lexer session\n:mode ieee deg\n:format auto\n$1 = 1e20\nans = 1e20\nbig = inf\n\
+             r = 0.1\nb(x) = x + 1\na(x) = b(x) * 2\n",
+            session
+        );
+
+        let mut loaded = Interpreter::new();
+        loaded.run("stale = 1").unwrap();
+        loaded.load_session(&session).unwrap();
+        assert_eq!(session, loaded.save_session());
+        assert_eq!(4.0, loaded.interpret("a(1)").unwrap());
+        assert!(loaded.variable("stale").is_none());
+
+        loaded
+            .load_session("# by hand\n\ntau = 2 * pi\n:mode warn")
+            .unwrap();
+        assert_eq!(Some(2.0 * PI), loaded.variable("tau"));
+        assert_eq!(FloatPolicy::Warn, loaded.float_policy());
+        assert_eq!(AngleUnit::Radians, loaded.angle_unit());
+
+        // a failing line leaves the session as it was
+        assert!(matches!(
+            loaded.load_session("x = 1\n:mode fast"),
+            Err(ConsoleError::Line(2, err)) if matches!(*err, ConsoleError::UnknownSetting(_))
+        ));
+        assert!(matches!(
+            loaded.load_session("$2 = 1.0"),
+            Err(ConsoleError::Line(1, _))
+        ));
+        assert!(loaded.variable("x").is_none());
+        assert_eq!(Some(2.0 * PI), loaded.variable("tau"));
+    }
+
+    #[test]
+    fn sessions_keep_functions_with_constant_bodies() {
+        let mut inter = Interpreter::new();
+        inter.run("c(x) = 2").unwrap();
+
+        let mut loaded = Interpreter::new();
+        loaded.load_session(&inter.save_session()).unwrap();
+        assert_eq!("c(x) = 2", loaded.function("c").unwrap().to_string());
+        assert_eq!(2.0, loaded.interpret("c(5)").unwrap());
+
+        loaded.run_script("k() = 7").unwrap();
+        assert_eq!(7.0, loaded.interpret("k()").unwrap());
+    }
+
+    #[test]
+    fn functions_can_not_call_themselves_through_others() {
+        let mut inter = Interpreter::new();
+        inter.run("b(x) = x; a(x) = b(x)").unwrap();
+
+        assert!(matches!(
+            inter.run("b(x) = a(x)"),
+            Err(ParseError::RecursiveFunction(name)) if name == "b"
+        ));
+        assert!(matches!(
+            inter.run("c(x) = d(x)"),
+            Err(ParseError::UnknownIdentifier(name, _)) if name == "d"
+        ));
+    }
+
+    #[cfg(feature = "repl")]
+    #[test]
+    fn console_saves_and_loads_sessions() {
+        let path = std::env::temp_dir().join(format!("arth_session_{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        let input = format!("x = 3\n:save {path}\n:reset\n:load {path}\nx\n:load\n");
+        let (mut output, mut errors) = (Vec::new(), Vec::new());
+
+        let mut inter = Interpreter::new();
+        assert!(!inter
+            .run_lines(input.as_bytes(), &mut output, &mut errors)
+            .unwrap());
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            format!("3.0\nSaved the session to `{path}`\nLoaded the session from `{path}`\n3.0\n"),
+            String::from_utf8(output).unwrap()
+        );
+        assert_eq!(
+            "Error: Usage: `:load file`\n",
+            String::from_utf8(errors).unwrap()
+        );
+    }
+
+    #[test]
+    fn number_format_rounds_to_significant_digits() {
+        let full = NumberFormat::default();
+        assert_eq!("0.1", full.format(0.1));
+        assert_eq!("1e20", full.format(1e20));
+
+        let three = NumberFormat {
+            precision: Some(3),
+            ..NumberFormat::default()
+        };
+        assert_eq!("1230.0", three.format(1234.5));
+        assert_eq!("0.0123", three.format(0.012345));
+        assert_eq!("100.0", three.format(99.99));
+        assert_eq!("-2.0", three.format(-2.0));
+        assert_eq!("0.0", three.format(0.0));
+        assert_eq!("1.5e-7", three.format(1.5e-7));
+        assert_eq!("1e20", three.format(1e20));
+        assert_eq!("inf", three.format(f32::INFINITY));
+
+        let six = NumberFormat {
+            precision: Some(6),
+            ..NumberFormat::default()
+        };
+        assert_eq!("0.3", six.format(0.1 + 0.2));
+    }
+
+    #[test]
+    fn number_format_writes_each_notation() {
+        let format = |settings: &str| {
+            let mut format = NumberFormat::default();
+            for setting in settings.split_whitespace() {
+                format.set(setting).unwrap();
+            }
+            return format;
+        };
+
+        assert_eq!("1.2345e3", format("sci").format(1234.5));
+        assert_eq!("1.5e-4", format("sci").format(0.00015));
+        assert_eq!("12.5e3", format("eng").format(12500.0));
+        assert_eq!("4.7e-3", format("eng").format(0.0047));
+        assert_eq!("4.7k", format("si").format(4700.0));
+        assert_eq!("-4.7k", format("si").format(-4700.0));
+        assert_eq!("12µ", format("si").format(0.000012));
+        assert_eq!("4.70k", format("si .2").format(4700.0));
+        // rounding up carries into the next prefix
+        assert_eq!("1k", format("si 3").format(999.96));
+        // past the last prefix the exponent is written
+        assert_eq!("10e-36", format("si").format(1e-35));
+
+        assert_eq!("100000000000000000000.0", format("fixed").format(1e20));
+        assert_eq!("1,234,567.0", format("fixed group").format(1234567.0));
+        assert_eq!("1_234_567.50", format(".2 group=_").format(1234567.5));
+        assert_eq!("2.00", format(".2").format(2.0));
+        assert_eq!("3", format(".0").format(2.7));
+
+        assert_eq!("0.29999998", format("auto").format(0.7 - 0.4));
+        assert_eq!("0.3", format("smart").format(0.7 - 0.4));
+        assert_eq!("0.3333333", format("smart").format(1.0 / 3.0));
+        assert_eq!("0.29999998", format("smart exact").format(0.7 - 0.4));
+
+        assert_eq!(Notation::Engineering, format("engineering").notation);
+        assert_eq!("sci 4 group smart", format("smart group 4 sci").to_string());
+        assert_eq!("auto .2 group=_", format(".2 group=_").to_string());
+
+        let mut number_format = NumberFormat::default();
+        for setting in ["group= ", "group=\t", "group=\u{a0}"] {
+            assert!(matches!(
+                number_format.set(setting),
+                Err(ConsoleError::UnknownSetting(_))
+            ));
+        }
+        assert_eq!(None, number_format.separator);
+        assert!(matches!(
+            NumberFormat::default().set("fast"),
+            Err(ConsoleError::UnknownSetting(setting)) if setting == "fast"
+        ));
+
+        let (source, si) = NumberFormat::default()
+            .split_suffix("2 * 1000 as si")
+            .unwrap();
+        assert_eq!(("2 * 1000", "2k"), (source, si.format(2000.0).as_str()));
+        assert!(NumberFormat::default().split_suffix("2 as fast").is_err());
+
+        // read on every keystroke, so digits that can not be written are
+        // rejected rather than left to the formatter
+        for source in [
+            "1 as .3000000000",
+            "1 as 3000000000",
+            "1 as sci .18446744073709551615",
+        ] {
+            assert!(matches!(
+                NumberFormat::default().split_suffix(source),
+                Err(ConsoleError::UnknownSetting(_))
+            ));
+        }
+        assert_eq!("1.00000001e-1", format("sci 9").format(0.1));
+        assert!(NumberFormat::default().set("10").is_err());
+        assert!(NumberFormat::default().set(".46").is_err());
+        let unbounded = NumberFormat {
+            notation: Notation::Scientific,
+            decimals: Some(usize::MAX),
+            ..NumberFormat::default()
+        };
+        assert!(unbounded.format(1.0).starts_with("1.000"));
+
+        // the format is part of a session
+        let mut inter = Interpreter::new();
+        inter.run_script(":format eng 4 group").unwrap();
+        assert_eq!(format("eng 4 group"), inter.number_format());
+
+        let mut loaded = Interpreter::new();
+        loaded.load_session(&inter.save_session()).unwrap();
+        assert_eq!(format("eng 4 group"), loaded.number_format());
+    }
+
+    #[cfg(feature = "repl")]
+    #[test]
+    fn run_lines_formats_results() {
+        let mut inter = Interpreter::new();
+        let input =
+            ":format sci 3\n1234.5\n0.7 - 0.4 as auto smart\n2 as fixed .2\n1 as fast\n:format\n";
+        let (mut output, mut errors) = (Vec::new(), Vec::new());
+
+        assert!(!inter
+            .run_lines(input.as_bytes(), &mut output, &mut errors)
+            .unwrap());
+        // `as` only changes the result it follows
+        assert_eq!(
+            "number format: sci 3\n1.23e3\n0.3\n2.00\nnumber format: sci 3\n",
+            String::from_utf8(output).unwrap()
+        );
+        assert_eq!(
+            "Error: Unknown setting: `fast`\n",
+            String::from_utf8(errors).unwrap()
+        );
+    }
+
+    #[test]
+    fn scripts_run_line_by_line() {
+        let mut inter = Interpreter::new();
+        let script = "# shared constants\n:mode deg\n\ng = 9.81\nfall(t) = (g * t *\n  t) / 2\n";
+
+        inter.run_script(script).unwrap();
+        assert_eq!(AngleUnit::Degrees, inter.angle_unit());
+        assert_eq!(Some(9.81), inter.variable("g"));
+        assert_eq!(
+            "fall(t) = (g * t * t) / 2",
+            inter.function("fall").unwrap().to_string()
+        );
+
+        assert!(matches!(
+            inter.run_script("h = 1\n\nh +\n"),
+            Err(ConsoleError::Line(3, err))
+                if matches!(*err, ConsoleError::Parse(ParseError::MissingExpression))
+        ));
+        // lines before the failing one have run
+        assert_eq!(Some(1.0), inter.variable("h"));
+    }
+
+    #[cfg(feature = "repl")]
+    #[test]
+    fn config_sets_modes_and_runs_the_prelude() {
+        let dir = std::env::temp_dir().join(format!("arth_config_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("team.arth"), "c = 299792458\nkm(m) = m / 1000\n").unwrap();
+        std::fs::write(
+            dir.join("config"),
+            "# team settings\nmode = warn\nangle = deg\nprecision = 4\n\
+             color = off\nprompt = \"calc {n}> \"\nprelude = team.arth\n",
+        )
+        .unwrap();
+
+        let config = crate::Config::read(&dir.join("config")).unwrap();
+        let mut inter = Interpreter::new();
+        config.apply(&mut inter).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(!config.color);
+        assert_eq!("calc 3> ", config.prompt_for(3));
+        assert_eq!(FloatPolicy::Warn, inter.float_policy());
+        assert_eq!(AngleUnit::Degrees, inter.angle_unit());
+        assert_eq!(
+            "299800.0",
+            inter
+                .number_format()
+                .format(inter.interpret("km(c)").unwrap())
+        );
+
+        let unknown =
+            |err: &ConsoleError| matches!(err, ConsoleError::UnknownSetting(key) if key == "speed");
+        assert!(matches!(
+            crate::Config::parse("mode = warn\nspeed = 2"),
+            Err(ConsoleError::Line(2, err)) if unknown(&err)
+        ));
+        assert!(crate::Config::parse("precision = many").is_err());
+        assert!(crate::Config::parse("precision = sci").is_err());
+        assert!(matches!(
+            crate::Config::parse("precision = 3000000000"),
+            Err(ConsoleError::Line(1, err))
+                if matches!(&*err, ConsoleError::UnknownSetting(setting)
+                    if setting == "precision = 3000000000")
+        ));
+        assert_eq!(crate::Config::default(), crate::Config::parse("").unwrap());
+    }
 }
@@ -1,17 +1,55 @@
-use ast::interpreter::Interpreter;
+#![allow(clippy::needless_return)]
 
-mod ast;
-
-//todo:
-//parse floats i.e 1.233 and negative numbers i.e -6.3 and expressions like 1 - -5
-//make sin,cos etc usable
-//error report on 0 division
-//clean up
+use arth_lexer::{Config, ConsoleError, Event, Interpreter, Level};
+use colored::Colorize;
+use std::path::Path;
 
 fn main() -> std::io::Result<()> {
     let mut inter = Interpreter::new();
 
-    inter.console()?;
+    // ARTH_LOG=debug (or trace) shows what the lexer, parser and evaluator
+    // do; warnings and errors are printed by the console either way
+    let level = std::env::var("ARTH_LOG")
+        .ok()
+        .and_then(|name| Level::from_name(&name))
+        .unwrap_or(Level::Off);
+
+    inter.set_subscriber(level, print_event);
+
+    // ARTH_CONFIG=path reads that file instead of the one in the config directory
+    let config = match std::env::var_os("ARTH_CONFIG") {
+        Some(path) => Config::read(Path::new(&path)),
+        None => Config::load(),
+    };
+    let config = config.unwrap_or_else(|err| {
+        print_error("Config", &err);
+        Config::default()
+    });
+
+    if let Err(err) = config.apply(&mut inter) {
+        print_error("Prelude", &err);
+    }
+
+    // a failing line makes the exit status 1, as with a shell script
+    if !inter.console_with(&config)? {
+        std::process::exit(1);
+    }
 
     return Ok(());
 }
+
+/// A problem with the config or prelude, which the console starts without.
+fn print_error(source: &str, err: &ConsoleError) {
+    eprintln!(
+        "{}",
+        format!("{}: {source}: {}", "Error".underline(), err.message()).red()
+    )
+}
+
+/// Events go to stderr, leaving stdout to results when input is piped in.
+fn print_event(event: &Event) {
+    eprintln!(
+        "{}",
+        format!("[{}] {}", event.stage, event.message).dimmed()
+    )
+}
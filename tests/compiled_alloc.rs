@@ -0,0 +1,46 @@
+//! `CompiledExpr::eval` is meant for hot loops, so it must not allocate.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use arth_lexer::Interpreter;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+#[test]
+fn eval_does_not_allocate() {
+    let mut inter = Interpreter::new();
+    let compiled = inter
+        .compile("2 * pi * r + sin r * (r - 1) / 3 ^ 2")
+        .unwrap();
+
+    let mut sum = 0.0;
+    let before = ALLOCATIONS.load(Ordering::SeqCst);
+
+    for i in 0..1_000 {
+        sum += compiled.eval(&[i as f32]).unwrap();
+    }
+
+    let after = ALLOCATIONS.load(Ordering::SeqCst);
+
+    assert!(sum.is_finite());
+    assert_eq!(before, after);
+}
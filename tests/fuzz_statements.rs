@@ -0,0 +1,149 @@
+//! Random statements, definitions and calls must fail cleanly within the
+//! limits set, never panic and never run away with memory.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    ptr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use arth_lexer::{FloatPolicy, Interpreter, Limits};
+
+/// Past this many live bytes allocation fails, which aborts the test.
+const MEMORY_CEILING: usize = 256 << 20;
+
+struct CappedAllocator;
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CappedAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if LIVE.fetch_add(layout.size(), Ordering::SeqCst) + layout.size() > MEMORY_CEILING {
+            LIVE.fetch_sub(layout.size(), Ordering::SeqCst);
+            return ptr::null_mut();
+        }
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE.fetch_sub(layout.size(), Ordering::SeqCst);
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CappedAllocator = CappedAllocator;
+
+const FRAGMENTS: &[&str] = &[
+    "0",
+    "1",
+    "2.5",
+    "99999999999",
+    "+",
+    "-",
+    "*",
+    "/",
+    "^",
+    "(",
+    ")",
+    " ",
+    "sin",
+    "log",
+    "pi",
+    "x",
+    "y",
+    "f",
+    "g",
+    "f(",
+    "g(",
+    "f(x)",
+    "g(x, y)",
+    "h(",
+    "f(f(f(f(",
+    "))))",
+    "f(f(f(f(f(f(f(f(1))))))))",
+    "x+x+x+x+x+x+x+x",
+    "=",
+    ",",
+    ";",
+    "\n",
+    "$1",
+    "$2",
+    "ans",
+    " as ",
+    "sci",
+    ".3",
+    ":format",
+    ":mode",
+    "#",
+    "é",
+];
+
+/// Starts of statements, so that assignments and definitions are common
+/// enough for later inputs to call what earlier ones defined.
+const HEADS: &[&str] = &["", "", "x = ", "y = ", "f(x) = ", "g(x, y) = ", "h(x) = "];
+
+/// Small xorshift generator so the corpus is reproducible without extra dependencies.
+struct Xorshift(u64);
+
+impl Xorshift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+
+    fn input(&mut self) -> String {
+        let head = HEADS[self.below(HEADS.len())];
+        let len = self.below(16);
+
+        (0..len).fold(head.to_string(), |input, _| {
+            input + FRAGMENTS[self.below(FRAGMENTS.len())]
+        })
+    }
+}
+
+#[test]
+fn statements_stay_within_limits() {
+    let limits = Limits {
+        max_input_length: Some(256),
+        max_tokens: Some(1_000),
+        max_depth: Some(64),
+        max_steps: Some(100_000),
+        max_call_depth: Some(8),
+        #[cfg(feature = "std")]
+        timeout: Some(std::time::Duration::from_millis(50)),
+    };
+    let mut defined = 0;
+
+    for policy in [FloatPolicy::Strict, FloatPolicy::Ieee, FloatPolicy::Warn] {
+        let mut rng = Xorshift(0x2545_f491_4f6c_dd1d);
+        let mut inter = Interpreter::new();
+        inter.set_float_policy(policy);
+        inter.set_limits(limits);
+        inter.set_cache_capacity(16);
+
+        for _ in 0..20_000 {
+            let input = rng.input();
+
+            let _ = inter.preview(&input);
+            let _ = inter.diagnose(&input);
+            let _ = inter.run_script(&input);
+
+            match inter.run(&input) {
+                Ok(Some(value)) => {
+                    inter.record_result(value);
+                }
+                Ok(None) => defined += 1,
+                Err(_) => {}
+            }
+        }
+    }
+
+    assert!(defined > 100, "{defined}");
+}
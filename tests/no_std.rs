@@ -0,0 +1,28 @@
+//! Builds the library the way an embedded target would see it, so anything
+//! std-only that creeps into the core fails here rather than on the device.
+
+use std::process::Command;
+
+#[test]
+fn core_builds_without_std() {
+    let target_dir = concat!(env!("CARGO_TARGET_TMPDIR"), "/no_std");
+
+    let output = Command::new(env!("CARGO"))
+        .args([
+            "build",
+            "--lib",
+            "--no-default-features",
+            "--features",
+            "libm",
+        ])
+        .args(["--target-dir", target_dir])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .expect("failed to run cargo");
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}